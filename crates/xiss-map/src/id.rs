@@ -2,13 +2,34 @@ use std::fmt;
 
 use smol_str::SmolStr;
 
-#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd)]
 pub enum IdKind {
     Class,
     Var,
     Keyframes,
 }
 
+impl IdKind {
+    /// Returns a char that is used to encode [IdKind] in css map files.
+    pub fn to_char(self) -> char {
+        match self {
+            IdKind::Class => 'C',
+            IdKind::Var => 'V',
+            IdKind::Keyframes => 'K',
+        }
+    }
+
+    /// Decodes [IdKind] from a css map char.
+    pub fn from_char(c: char) -> Option<IdKind> {
+        match c {
+            'C' => Some(IdKind::Class),
+            'V' => Some(IdKind::Var),
+            'K' => Some(IdKind::Keyframes),
+            _ => None,
+        }
+    }
+}
+
 impl fmt::Display for IdKind {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
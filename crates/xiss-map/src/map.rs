@@ -70,7 +70,12 @@ impl Map {
                 module_index = i;
             }
             if let Some(module) = self.get_mut_by_index(module_index) {
-                let id = Id::new(kind, module.id.clone(), local_id.into(), global_id.into());
+                let id = Id::new(
+                    kind,
+                    module.id.clone(),
+                    SmolStr::new(local_id),
+                    global_id.into(),
+                );
                 match kind {
                     IdKind::Class => insert_id(&mut module.classes, id)?,
                     IdKind::Var => insert_id(&mut module.vars, id)?,
@@ -1,4 +1,10 @@
-use std::{error, fmt, io, str::CharIndices};
+use std::{
+    borrow::Cow,
+    error,
+    fmt::{self, Write},
+    io,
+    str::CharIndices,
+};
 
 use crate::id::IdKind;
 
@@ -69,7 +75,7 @@ impl<'a> Parser<'a> {
 
     pub fn next_id(
         &mut self,
-    ) -> Result<Option<(IdKind, Option<&'a str>, &'a str, &'a str)>, Error> {
+    ) -> Result<Option<(IdKind, Option<&'a str>, Cow<'a, str>, &'a str)>, Error> {
         if let Some((kind, module_id, local_id, global_id)) =
             parse_line(&self.s, &mut self.iter).map_err(|err| err.with_line_num(self.line_num))?
         {
@@ -87,10 +93,10 @@ impl<'a> Parser<'a> {
     }
 }
 
-fn parse_line<'a>(
-    s: &'a str,
-    iter: &mut CharIndices<'a>,
-) -> ParseResult<Option<(IdKind, &'a str, &'a str, &'a str)>> {
+/// ID kind, module ID, local ID and global ID of a map line.
+type Line<'a> = (IdKind, &'a str, Cow<'a, str>, &'a str);
+
+fn parse_line<'a>(s: &'a str, iter: &mut CharIndices<'a>) -> ParseResult<Option<Line<'a>>> {
     if let (2, kind) = parse_id_kind(iter)? {
         let (i, module_id) = parse_module_id(s, iter)?;
         let (i, local_id) = parse_local_id(s, i, iter)?;
@@ -103,11 +109,10 @@ fn parse_line<'a>(
 
 fn parse_id_kind<'a>(iter: &mut CharIndices<'a>) -> ParseResult<(usize, IdKind)> {
     let kind = if let Some((i, c)) = iter.next() {
-        match c {
-            'C' => IdKind::Class,
-            'V' => IdKind::Var,
-            'K' => IdKind::Keyframes,
-            _ => return Err(Error::new(ErrorKind::InvalidChar(i, c))),
+        if let Some(kind) = IdKind::from_char(c) {
+            kind
+        } else {
+            return Err(Error::new(ErrorKind::InvalidChar(i, c)));
         }
     } else {
         return Ok((0, IdKind::Class));
@@ -144,31 +149,101 @@ fn parse_module_id<'a>(s: &'a str, iter: &mut CharIndices<'a>) -> ParseResult<(u
     Err(Error::new(ErrorKind::UnexpectedEOL))
 }
 
+/// Parses a local ID, local IDs are CSS identifiers with escaped characters.
+///
+/// Unescaped local ID is returned as an owned string when it contains escape
+/// sequences.
 fn parse_local_id<'a>(
     s: &'a str,
     start: usize,
     iter: &mut CharIndices<'a>,
-) -> ParseResult<(usize, &'a str)> {
-    if let Some((i, c)) = iter.next() {
+) -> ParseResult<(usize, Cow<'a, str>)> {
+    let mut unescaped: Option<String> = None;
+    let mut is_first = true;
+    while let Some((i, c)) = iter.next() {
         match c {
-            'a'..='z' | 'A'..='Z' | '_' => {
-                for (i, c) in iter {
-                    match c {
-                        'a'..='z' | 'A'..='Z' | '0'..='9' | '_' => {}
-                        ',' => {
-                            return Ok((i + 1, &s[start..i]));
-                        }
-                        _ => return Err(Error::new(ErrorKind::InvalidChar(i, c))),
-                    }
-                }
+            'a'..='z' | 'A'..='Z' | '_' | '-' => {}
+            '0'..='9' if !is_first => {}
+            ',' if !is_first => {
+                let local_id = if let Some(unescaped) = unescaped {
+                    Cow::Owned(unescaped)
+                } else {
+                    Cow::Borrowed(&s[start..i])
+                };
+                return Ok((i + 1, local_id));
             }
+            '\\' => {
+                let c = parse_escape(iter)?;
+                unescaped
+                    .get_or_insert_with(|| s[start..i].to_string())
+                    .push(c);
+                is_first = false;
+                continue;
+            }
+            c if !c.is_ascii() => {}
             _ => return Err(Error::new(ErrorKind::InvalidChar(i, c))),
         }
+        if let Some(unescaped) = &mut unescaped {
+            unescaped.push(c);
+        }
+        is_first = false;
     }
 
     Err(Error::new(ErrorKind::UnexpectedEOL))
 }
 
+/// Parses an escape sequence after a `\` char.
+///
+/// Hex escapes contain up to 6 hex digits and an optional whitespace, invalid
+/// code points are replaced with U+FFFD like in CSS.
+fn parse_escape<'a>(iter: &mut CharIndices<'a>) -> ParseResult<char> {
+    match iter.next() {
+        Some((_, c)) if c.is_ascii_hexdigit() => {
+            let mut code = c.to_digit(16).unwrap();
+            for _ in 0..5 {
+                let mut next = iter.clone();
+                match next.next() {
+                    Some((_, c)) if c.is_ascii_hexdigit() => {
+                        code = code * 16 + c.to_digit(16).unwrap();
+                        *iter = next;
+                    }
+                    _ => break,
+                }
+            }
+            let mut next = iter.clone();
+            if let Some((_, ' ')) = next.next() {
+                *iter = next;
+            }
+            Ok(match code {
+                0 => char::REPLACEMENT_CHARACTER,
+                code => char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER),
+            })
+        }
+        Some((i, '\n')) => Err(Error::new(ErrorKind::InvalidChar(i, '\n'))),
+        Some((_, c)) => Ok(c),
+        None => Err(Error::new(ErrorKind::UnexpectedEOL)),
+    }
+}
+
+/// Writes a local ID to a css map, characters that can't be used in
+/// identifiers are escaped.
+pub fn write_local_id(output: &mut String, local_id: &str) {
+    for (i, c) in local_id.chars().enumerate() {
+        match c {
+            'a'..='z' | 'A'..='Z' | '_' | '-' => output.push(c),
+            '0'..='9' if i > 0 => output.push(c),
+            c if !c.is_ascii() => output.push(c),
+            c if c.is_ascii_graphic() && !c.is_ascii_digit() => {
+                output.push('\\');
+                output.push(c);
+            }
+            c => {
+                write!(output, "\\{:x} ", c as u32).unwrap();
+            }
+        }
+    }
+}
+
 fn parse_global_id<'a>(
     s: &'a str,
     start: usize,
@@ -293,4 +368,66 @@ mod tests {
             test('a', Some('/'), Some('-'));
         }
     }
+
+    mod local_id {
+        use super::super::*;
+
+        fn parse_local(line: &str) -> ParseResult<Option<(IdKind, &str, Cow<'_, str>, &str)>> {
+            let mut iter = line.char_indices();
+            parse_line(line, &mut iter)
+        }
+
+        #[test]
+        fn kebab_case() {
+            let (_, _, local_id, global_id) =
+                parse_local("C,m,button-primary,g\n").unwrap().unwrap();
+            assert_eq!(local_id, "button-primary");
+            assert!(matches!(local_id, Cow::Borrowed(_)));
+            assert_eq!(global_id, "g");
+        }
+
+        #[test]
+        fn escaped_chars() {
+            let (_, _, local_id, _) = parse_local("C,m,sm\\:p-2\\.5,g\n").unwrap().unwrap();
+            assert_eq!(local_id, "sm:p-2.5");
+        }
+
+        #[test]
+        fn hex_escapes() {
+            let (_, _, local_id, _) = parse_local("C,m,\\31 0,g\n").unwrap().unwrap();
+            assert_eq!(local_id, "10");
+            let (_, _, local_id, _) = parse_local("C,m,a\\2c b,g\n").unwrap().unwrap();
+            assert_eq!(local_id, "a,b");
+            let (_, _, local_id, _) = parse_local("C,m,a\\0,g\n").unwrap().unwrap();
+            assert_eq!(local_id, "a\u{FFFD}");
+        }
+
+        #[test]
+        fn invalid_chars() {
+            assert!(parse_local("C,m,1a,g\n").is_err());
+            assert!(parse_local("C,m,a:b,g\n").is_err());
+            assert!(parse_local("C,m,,g\n").is_err());
+            assert!(parse_local("C,m,a\\").is_err());
+        }
+
+        #[test]
+        fn write_and_parse() {
+            for id in [
+                "a",
+                "button-primary",
+                "sm:p-2.5",
+                "1a",
+                "a,b",
+                "a b",
+                "a\\b",
+                "ä",
+            ] {
+                let mut line = "C,m,".to_string();
+                write_local_id(&mut line, id);
+                line.push_str(",g\n");
+                let (_, _, local_id, _) = parse_local(&line).unwrap().unwrap();
+                assert_eq!(local_id, id);
+            }
+        }
+    }
 }
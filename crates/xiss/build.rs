@@ -0,0 +1,19 @@
+use std::{env, process::Command};
+
+fn main() {
+    // build manifest hashes aren't stable between toolchains, manifests are
+    // versioned with a rustc version
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let version = Command::new(rustc)
+        .arg("--version")
+        .output()
+        .ok()
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .unwrap_or_default();
+    println!("cargo:rustc-env=XISS_RUSTC_VERSION={}", version.trim());
+    println!(
+        "cargo:rustc-env=XISS_TARGET={}",
+        env::var("TARGET").unwrap_or_default()
+    );
+    println!("cargo:rerun-if-changed=build.rs");
+}
@@ -1,10 +1,27 @@
 use std::fmt::{self, Write};
 
 use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use swc_atoms::JsWord;
 
-#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+use crate::doc_comment::write_jsdoc;
+
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Hash,
+    clap::ValueEnum,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+)]
+#[serde(rename_all = "lowercase")]
 pub enum ClassMapOutput {
+    #[default]
     Inline,
     Table,
 }
@@ -108,8 +125,17 @@ impl ClassMap {
         Ok(())
     }
 
-    pub fn emit_ts<W: Write>(&self, output: &mut W) -> Result<(), std::fmt::Error> {
-        write!(output, "/** classmap {{@link {}}} */\n", self.name)?;
+    pub fn emit_ts<W: Write>(
+        &self,
+        output: &mut W,
+        doc: Option<&[String]>,
+    ) -> Result<(), std::fmt::Error> {
+        write_jsdoc(
+            output,
+            "",
+            format_args!("classmap {{@link {}}}", self.name),
+            doc,
+        )?;
         write!(output, "export function {}(", self.name)?;
         let mut iter = self.states.iter();
         if let Some(s) = iter.next() {
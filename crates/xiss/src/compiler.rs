@@ -1,12 +1,26 @@
-use std::{fmt::Write, path::Path, rc::Rc};
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Write},
+    mem::take,
+    ops::Range,
+    path::Path,
+    rc::Rc,
+};
 
+use clap::ValueEnum;
 use phf::phf_map;
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
 use swc_atoms::JsWord;
-use swc_common::{errors::HANDLER, util::take::Take, Span, DUMMY_SP};
+use swc_common::{
+    errors::{Handler, HANDLER},
+    util::take::Take,
+    BytePos, SourceFile, Span, DUMMY_SP,
+};
 use swc_css::{
     ast::*,
-    codegen::{writer::basic::BasicCssWriter, CodeGenerator, Emit},
+    codegen::{writer::basic::BasicCssWriter, CodeGenerator, CodegenConfig, Emit},
     visit::{VisitMut, VisitMutWith},
 };
 use tracing::error;
@@ -14,77 +28,289 @@ use xiss_map::IdKind;
 
 use crate::{
     class_map::{ClassMap, ClassMapOutput, ClassMapState},
-    css::process_css,
+    conditional::Conditionals,
+    const_map::ResolvedConsts,
+    css::{
+        parse_declaration_value, process_css, serialize_component_values,
+        serialize_declaration_value, Diagnostics,
+    },
     css_map::{CssMap, CssMapModule},
+    doc_comment::{write_jsdoc, DocComments},
+    eval::eval_value,
     id::Id,
+    loops::LoopExpansion,
+    mixin::{extract_local_mixins, ExpandMixins},
+    naming::TsNaming,
+    preprocess::{PreludeSubstitution, SourceError},
+    source_map::{LineIndex, LineWriter, Mapping},
+    theme::ThemeExpansion,
 };
 
+/// ID output type.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Hash,
+    clap::ValueEnum,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+)]
+pub enum IdOutput {
+    /// `export const enum c {}` in a `.d.ts` file, enums are inlined by
+    /// `tsc`.
+    #[default]
+    #[serde(rename = "constEnum")]
+    #[value(name = "constEnum")]
+    ConstEnum,
+    /// `export const c = {}` in a `.js` file with `.d.ts` declarations, it
+    /// works with tools that compile files in isolation (esbuild, swc,
+    /// Babel).
+    #[serde(rename = "object")]
+    #[value(name = "object")]
+    Object,
+}
+
+impl fmt::Display for IdOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_possible_value().unwrap().get_name().fmt(f)
+    }
+}
+
+/// Compiler options that affect compilation artifacts.
+#[derive(Debug, Clone, Hash)]
+pub struct CompilerOptions {
+    pub class_map: ClassMapOutput,
+    pub minify: bool,
+    /// Values for `flag()` conditions and `define()` functions.
+    pub defines: BTreeMap<String, String>,
+    /// Naming transform for TS enum members.
+    pub ts_naming: TsNaming,
+    /// ID output type.
+    pub id_output: IdOutput,
+    /// Emit `.d.ts.map` declaration maps.
+    pub declaration_map: bool,
+}
+
+/// Formats errors reported by passes that transform module source with
+/// one-based line and column positions in `source`.
+///
+/// Passes are reporting offsets in their input, which is an output of the
+/// previous pass, so positions are shifted from the module source when
+/// previous passes expanded anything.
+fn source_errors(path: &Path, source: &str, errors: &[SourceError]) -> Vec<String> {
+    errors
+        .iter()
+        .map(|err| {
+            let before = &source[..err.offset];
+            let line = before.matches('\n').count();
+            let col = before.rsplit('\n').next().unwrap_or("").chars().count();
+            format!(
+                "{}:{}:{}: {}\n",
+                path.display(),
+                line + 1,
+                col + 1,
+                err.message
+            )
+        })
+        .collect()
+}
+
+/// Compiles a module.
+///
+/// IDs are allocated in the [CssMap] even when compilation fails, callers
+/// should wrap compilation in a [CssMap] transaction and roll it back on
+/// errors.
 pub fn compile<P: AsRef<Path>>(
     path: P,
     contents: String,
     css_map: &mut CssMap,
-    const_map: &FxHashMap<JsWord, Vec<ComponentValue>>,
+    consts: &ResolvedConsts,
     module_id: &str,
-    class_map_output: ClassMapOutput,
-) -> Result<CompilationArtifact, String> {
-    process_css(path, contents, |handler, stylesheet| {
-        let mut css = String::new();
-        let mut js = String::new();
-        let mut ts = String::new();
-
-        stylesheet.visit_mut_with(&mut UpdateConstValues { const_map });
-        if !handler.has_errors() {
-            let module_index = css_map.get_module_index(module_id);
-            let mut module_compiler = ModuleCompiler::new(css_map, module_index);
-            stylesheet.visit_mut_with(&mut module_compiler);
+    options: &CompilerOptions,
+) -> Result<CompilationArtifact, Diagnostics> {
+    let path = path.as_ref();
+    let mut conditionals = Conditionals::new(&options.defines);
+    let expanded = conditionals.expand(&contents);
+    let mut errors = source_errors(path, &contents, &conditionals.errors);
+    let contents = expanded.unwrap_or(contents);
+    let mut loops = LoopExpansion::default();
+    let expanded = loops.expand(&contents);
+    errors.extend(source_errors(path, &contents, &loops.errors));
+    let contents = expanded.unwrap_or(contents);
+    let mut themes = ThemeExpansion::default();
+    let expanded = themes.expand(&contents);
+    errors.extend(source_errors(path, &contents, &themes.errors));
+    let contents = expanded.unwrap_or(contents);
+    let const_map = &consts.values;
+    let mut prelude_substitution = PreludeSubstitution::new(
+        |name| {
+            const_map
+                .get(&JsWord::from(name))
+                .map(|value| serialize_component_values(value))
+        },
+        |name| consts.custom_media.get(&JsWord::from(name)).cloned(),
+    );
+    let substituted = prelude_substitution.substitute(&contents);
+    errors.extend(source_errors(path, &contents, &prelude_substitution.errors));
+    if !errors.is_empty() {
+        return Err(Diagnostics {
+            message: errors.concat(),
+            errors: errors.len(),
+            warnings: 0,
+        });
+    }
+    let contents = substituted.unwrap_or(contents);
+
+    let doc_comments = DocComments::extract(&contents);
+    let (mut artifact, diagnostics) =
+        process_css(path, contents, |handler, source_file, stylesheet| {
+            let mut css = String::new();
+            let mut js = String::new();
+            let mut ts = String::new();
+            let mut ts_mappings = Vec::new();
+
+            remove_module_declaration(stylesheet);
+
+            // mixins are expanded before const values are substituted, so that
+            // const values can be used in mixin arguments
+            let mut expand_mixins = ExpandMixins::new(&consts.mixins);
+            extract_local_mixins(stylesheet, &mut expand_mixins);
+            stylesheet.visit_mut_with(&mut expand_mixins);
+            let mut mixins: Vec<JsWord> = expand_mixins.used.into_iter().collect();
+            mixins.sort();
+
+            let mut update_const_values = UpdateConstValues::new(const_map);
+            extract_local_consts(stylesheet, &mut update_const_values);
+            stylesheet.visit_mut_with(&mut update_const_values);
+            let mut used_consts = update_const_values.used;
+            used_consts.extend(
+                prelude_substitution
+                    .used_consts
+                    .iter()
+                    .map(|name| JsWord::from(name.as_str())),
+            );
+            let mut consts: Vec<JsWord> = used_consts.into_iter().collect();
+            consts.sort();
+            let mut custom_media: Vec<JsWord> = prelude_substitution
+                .used_custom_media
+                .iter()
+                .map(|name| JsWord::from(name.as_str()))
+                .collect();
+            custom_media.sort();
+            custom_media.dedup();
+            let mut ids = Vec::new();
             if !handler.has_errors() {
-                if module_compiler.has_keyframes {
-                    stylesheet.visit_mut_with(&mut TransformAnimationNames::new(
-                        &module_compiler.scope.keyframes,
-                    ));
-                }
-
-                if let Err(err) = emit_js(&mut js, &module_compiler.class_maps, class_map_output) {
-                    handler.err(&format!("Failed to emit js: {}", err));
-                }
+                let module_index = css_map.get_module_index(module_id);
+                let mut module_compiler = ModuleCompiler::new(css_map, module_index);
+                stylesheet.visit_mut_with(&mut module_compiler);
+                if !handler.has_errors() {
+                    if module_compiler.has_keyframes {
+                        stylesheet.visit_mut_with(&mut TransformAnimationNames::new(
+                            &module_compiler.scope.keyframes,
+                        ));
+                    }
 
-                let mut classes: Vec<(&JsWord, &Rc<Id>)> =
-                    module_compiler.scope.classes.iter().collect();
-                classes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-
-                let mut vars: Vec<(&JsWord, &Rc<Id>)> = module_compiler.scope.vars.iter().collect();
-                vars.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-
-                let mut keyframes: Vec<(&JsWord, &Rc<Id>)> =
-                    module_compiler.scope.keyframes.iter().collect();
-                keyframes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
-
-                if let Err(err) = emit_ts(
-                    &mut ts,
-                    &classes,
-                    &vars,
-                    &keyframes,
-                    module_index,
-                    &module_compiler.class_maps,
-                    &module_compiler.scope.css_map.modules,
-                ) {
-                    handler.err(&format!("Failed to emit types: {}", err));
+                    let mut classes: Vec<(&JsWord, &Rc<Id>)> =
+                        module_compiler.scope.classes.iter().collect();
+                    classes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+                    let mut vars: Vec<(&JsWord, &Rc<Id>)> =
+                        module_compiler.scope.vars.iter().collect();
+                    vars.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+                    let mut keyframes: Vec<(&JsWord, &Rc<Id>)> =
+                        module_compiler.scope.keyframes.iter().collect();
+                    keyframes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+                    let id_spans = &module_compiler.id_spans;
+                    let class_members = ts_members(handler, &classes, options.ts_naming, id_spans);
+                    let var_members = ts_members(handler, &vars, options.ts_naming, id_spans);
+                    let keyframes_members =
+                        ts_members(handler, &keyframes, options.ts_naming, id_spans);
+                    let mut ctx = EmitContext {
+                        classes: &class_members,
+                        vars: &var_members,
+                        keyframes: &keyframes_members,
+                        themes: &themes.names,
+                        module_index,
+                        class_maps: &module_compiler.class_maps,
+                        modules: &module_compiler.scope.css_map.modules,
+                        class_map_spans: &module_compiler.class_map_spans,
+                        doc_comments: &doc_comments,
+                        id_output: options.id_output,
+                        declaration_map: DeclarationMap::new(source_file),
+                    };
+                    if let Err(err) = emit_js(&mut js, &ctx, options.class_map) {
+                        handler.err(&format!("Failed to emit js: {}", err));
+                    }
+                    if let Err(err) = emit_ts(&mut LineWriter::new(&mut ts), &mut ctx) {
+                        handler.err(&format!("Failed to emit types: {}", err));
+                    }
+                    ts_mappings = ctx.declaration_map.mappings;
+
+                    ids.extend(classes.iter().map(|(_, id)| (*id).clone()));
+                    ids.extend(vars.iter().map(|(_, id)| (*id).clone()));
+                    ids.extend(keyframes.iter().map(|(_, id)| (*id).clone()));
+
+                    let writer = BasicCssWriter::new(&mut css, None, Default::default());
+                    let mut gen = CodeGenerator::new(
+                        writer,
+                        CodegenConfig {
+                            minify: options.minify,
+                        },
+                    );
+                    if let Err(err) = gen.emit(stylesheet) {
+                        handler.err(&format!("Failed to emit css: {}", err));
+                    };
                 }
+            }
 
-                let writer = BasicCssWriter::new(&mut css, None, Default::default());
-                let mut gen = CodeGenerator::new(writer, Default::default());
-                if let Err(err) = gen.emit(stylesheet) {
-                    handler.err(&format!("Failed to emit css: {}", err));
-                };
+            if handler.has_errors() {
+                None
+            } else {
+                Some(CompilationArtifact {
+                    css,
+                    js,
+                    ts,
+                    ts_mappings,
+                    consts,
+                    custom_media,
+                    mixins,
+                    ids,
+                    diagnostics: Diagnostics::default(),
+                })
             }
-        }
+        })?;
+    artifact.diagnostics = diagnostics;
+    Ok(artifact)
+}
 
-        if handler.has_errors() {
-            None
-        } else {
-            Some(CompilationArtifact { css, js, ts })
+/// Removes `@module` declaration, module IDs are resolved before compilation.
+fn remove_module_declaration(stylesheet: &mut Stylesheet) {
+    let mut index = 0;
+    stylesheet.rules.retain(|rule| {
+        index += 1;
+        if let Rule::AtRule(at_rule) = rule {
+            if &at_rule.name == "module" {
+                if index > 1 {
+                    HANDLER.with(|handler| {
+                        handler
+                            .struct_span_err(
+                                at_rule.span,
+                                "@module should be declared before any other rules",
+                            )
+                            .emit();
+                    });
+                }
+                return false;
+            }
         }
-    })
+        true
+    });
 }
 
 static ID_KIND: phf::Map<&'static str, IdKind> = phf_map! {
@@ -384,26 +610,16 @@ fn parse_at_extern(at_rule: &AtRule) -> ParserResult<ExternSymbol> {
                 if let Some(&kind) = ID_KIND.get(&symbol_kind_ident) {
                     expect_whitespace(&mut iter, &values.span)?;
 
-                    let mut imported_iter = iter.clone();
+                    // local names don't have to be valid JS identifiers, TS enum
+                    // member names are transformed with [TsNaming]
                     let imported = expect_ident(&mut iter, &values.span, false)?;
                     let mut local = imported.clone();
                     expect_whitespace(&mut iter, &values.span)?;
 
                     if expect_keyword(&mut iter, "as", &values.span).is_ok() {
                         expect_whitespace(&mut iter, &values.span)?;
-                        local = expect_ident(&mut iter, &values.span, true)?;
+                        local = expect_ident(&mut iter, &values.span, false)?;
                         expect_whitespace(&mut iter, &values.span)?;
-                    } else {
-                        if !is_valid_js_ident(&local) {
-                            if let ComponentValue::PreservedToken(token) =
-                                imported_iter.next().unwrap()
-                            {
-                                return Err(ParserError::new(
-                                    token.span,
-                                    ParserErrorKind::ExpectedValidJSIdentifier,
-                                ));
-                            }
-                        }
                     }
 
                     expect_keyword(&mut iter, "from", &values.span)?;
@@ -485,7 +701,7 @@ fn parse_at_class_map(at_rule: &AtRule, scope: &mut ModuleScope) -> ParserResult
                     Token::AtKeyword { value, .. } => match &**value {
                         "static" => {
                             expect_whitespace(&mut iter, &block.span)?;
-                            static_classes = expect_identifier_list(&mut iter, &block.span, true)?;
+                            static_classes = expect_identifier_list(&mut iter, &block.span, false)?;
                             expect_semi(&mut iter, &block.span)?;
                         }
                         "exclude" => {
@@ -514,7 +730,7 @@ fn parse_at_class_map(at_rule: &AtRule, scope: &mut ModuleScope) -> ParserResult
                         skip_whitespace(&mut iter);
                         expect_colon(&mut iter, &block.span)?;
                         skip_whitespace(&mut iter);
-                        let mut classes = expect_identifier_list(&mut iter, &block.span, true)?;
+                        let mut classes = expect_identifier_list(&mut iter, &block.span, false)?;
                         expect_semi(&mut iter, &block.span)?;
 
                         // resolve class name identifiers
@@ -631,6 +847,15 @@ impl<'a> ModuleScope<'a> {
         }
     }
 
+    fn contains(&self, id_kind: IdKind, local_id: &JsWord) -> bool {
+        let map = match id_kind {
+            IdKind::Class => &self.classes,
+            IdKind::Var => &self.vars,
+            IdKind::Keyframes => &self.keyframes,
+        };
+        map.contains_key(local_id)
+    }
+
     fn get_id(&mut self, id_kind: IdKind, local_id: &JsWord) -> Rc<Id> {
         let map = match id_kind {
             IdKind::Class => &mut self.classes,
@@ -647,70 +872,263 @@ impl<'a> ModuleScope<'a> {
     }
 }
 
+/// Extracts module-local const values from `:const {}` blocks and removes
+/// them from the stylesheet.
+fn extract_local_consts(stylesheet: &mut Stylesheet, update_const_values: &mut UpdateConstValues) {
+    stylesheet.rules.retain_mut(|rule| {
+        if let Rule::QualifiedRule(rule) = rule {
+            if is_const_selector(&rule.prelude) {
+                for v in rule.block.value.iter_mut() {
+                    if let ComponentValue::Declaration(decl) = v {
+                        // local const values can use global and previously
+                        // declared local const values
+                        decl.visit_mut_with(update_const_values);
+                        if let DeclarationName::DashedIdent(ident) = &decl.name {
+                            update_const_values
+                                .local
+                                .insert(ident.value.clone(), decl.value.clone());
+                        } else {
+                            HANDLER.with(|handler| {
+                                handler
+                                    .struct_span_err(
+                                        decl.span,
+                                        "Invalid const name, const name should be a dashed \
+                                         identifier, e.g. '--CONST-VAR'",
+                                    )
+                                    .emit();
+                            });
+                        }
+                    }
+                }
+                return false;
+            }
+        }
+        true
+    });
+}
+
+/// Checks if selector is a `:const` selector.
+fn is_const_selector(prelude: &QualifiedRulePrelude) -> bool {
+    if let QualifiedRulePrelude::SelectorList(list) = prelude {
+        if let [complex] = &list.children[..] {
+            if let [ComplexSelectorChildren::CompoundSelector(compound)] = &complex.children[..] {
+                if compound.nesting_selector.is_none() && compound.type_selector.is_none() {
+                    if let [SubclassSelector::PseudoClass(pseudo)] =
+                        &compound.subclass_selectors[..]
+                    {
+                        return &pseudo.name.value == "const" && pseudo.children.is_none();
+                    }
+                }
+            }
+        }
+    }
+    false
+}
+
 struct UpdateConstValues<'a> {
     const_map: &'a FxHashMap<JsWord, Vec<ComponentValue>>,
+    /// Module-local const values, local values are shadowing global values.
+    local: FxHashMap<JsWord, Vec<ComponentValue>>,
+    /// Names of global const values that were used in the module.
+    used: FxHashSet<JsWord>,
+    /// `const()` functions were substituted in the current declaration.
+    substituted: bool,
 }
 
-impl VisitMut for UpdateConstValues<'_> {
-    fn visit_mut_declaration(&mut self, decl: &mut Declaration) {
-        let mut has_const = false;
-        for v in decl.value.iter() {
-            if let ComponentValue::Function(func) = v {
-                if let FunctionName::Ident(ident) = &func.name {
-                    if &ident.value == "const" {
-                        has_const = true;
+impl<'a> UpdateConstValues<'a> {
+    fn new(const_map: &'a FxHashMap<JsWord, Vec<ComponentValue>>) -> Self {
+        Self {
+            const_map,
+            local: FxHashMap::default(),
+            used: FxHashSet::default(),
+            substituted: false,
+        }
+    }
+}
+
+impl UpdateConstValues<'_> {
+    /// Returns a value of a `const(--NAME)` function, errors are reported
+    /// when a const value can't be found.
+    fn resolve(&mut self, func: &Function) -> Option<Vec<ComponentValue>> {
+        if let Some((name, span)) = const_name(&func.value) {
+            if let Some(value) = self.local.get(&name) {
+                return Some(value.clone());
+            } else if let Some(value) = self.const_map.get(&name) {
+                self.used.insert(name);
+                return Some(value.clone());
+            }
+            HANDLER.with(|handler| {
+                handler
+                    .struct_span_err(span, &format!("Cannot find a const value '--{}'", name))
+                    .emit();
+            });
+        } else {
+            HANDLER.with(|handler| {
+                handler
+                    .struct_span_err(
+                        func.span,
+                        "Invalid function argument, const function should have a dashed \
+                         identifier, e.g. '--CONST-VAR'",
+                    )
+                    .emit();
+            });
+        }
+        None
+    }
+
+    /// Replaces `const()` functions in a list of values.
+    fn substitute(&mut self, values: &mut Vec<ComponentValue>) {
+        if !values
+            .iter()
+            .any(|v| matches!(v, ComponentValue::Function(func) if is_const_function(func)))
+        {
+            return;
+        }
+        let mut r = Vec::with_capacity(values.len());
+        let mut iter = take(values).into_iter().peekable();
+        while let Some(v) = iter.next() {
+            match v {
+                ComponentValue::Function(func) if is_const_function(&func) => {
+                    self.substituted = true;
+                    if let Some(value) = self.resolve(&func) {
+                        // const values are declared in custom properties and
+                        // preserved as tokens, codegen doesn't insert spaces
+                        // between tokens
+                        if r.last().is_some_and(|v| !is_separator(v)) {
+                            r.push(whitespace(func.span));
+                        }
+                        r.extend(value);
+                        if iter.peek().is_some_and(|v| !is_separator(v)) {
+                            r.push(whitespace(func.span));
+                        }
                     }
                 }
+                v => r.push(v),
             }
         }
+        *values = r;
+    }
+}
 
-        if has_const {
-            let mut r = Vec::with_capacity(decl.value.len());
-            for v in decl.value.iter() {
-                if let ComponentValue::Function(func) = v {
-                    if let FunctionName::Ident(ident) = &func.name {
-                        if &ident.value == "const" {
-                            if let Some(ComponentValue::DashedIdent(ident)) = func.value.get(0) {
-                                if let Some(value) = self.const_map.get(&ident.value) {
-                                    r.extend(value.clone());
-                                } else {
-                                    HANDLER.with(|handler| {
-                                        handler
-                                            .struct_span_err(
-                                                ident.span,
-                                                &format!(
-                                                    "Cannot find a const value '{}'",
-                                                    ident.value
-                                                ),
-                                            )
-                                            .emit();
-                                    });
-                                }
-                            } else {
-                                HANDLER.with(|handler| {
-                                    handler
-                                        .struct_span_err(
-                                            func.span,
-                                            "Invalid function argument, const function should \
-                                             have a dashed identifier, e.g. '--CONST-VAR'",
-                                        )
-                                        .emit();
-                                });
-                            }
-                            continue;
-                        }
+fn is_separator(v: &ComponentValue) -> bool {
+    match v {
+        ComponentValue::PreservedToken(token_and_span) => {
+            matches!(
+                token_and_span.token,
+                Token::WhiteSpace { .. } | Token::Comma
+            )
+        }
+        ComponentValue::Delimiter(delimiter) => delimiter.value == DelimiterValue::Comma,
+        _ => false,
+    }
+}
+
+fn whitespace(span: Span) -> ComponentValue {
+    ComponentValue::PreservedToken(Box::new(TokenAndSpan {
+        span,
+        token: Token::WhiteSpace { value: " ".into() },
+    }))
+}
+
+/// Returns a const name without `--` prefix from `const()` arguments,
+/// arguments of functions in custom properties are preserved as tokens.
+fn const_name(args: &[ComponentValue]) -> Option<(JsWord, Span)> {
+    let mut iter = args.iter().filter(|v| {
+        !matches!(v, ComponentValue::PreservedToken(token_and_span)
+            if matches!(token_and_span.token, Token::WhiteSpace { .. }))
+    });
+    let result = match iter.next()? {
+        ComponentValue::DashedIdent(ident) => (ident.value.clone(), ident.span),
+        ComponentValue::PreservedToken(token_and_span) => match &token_and_span.token {
+            Token::Ident { value, .. } => (value.strip_prefix("--")?.into(), token_and_span.span),
+            _ => return None,
+        },
+        _ => return None,
+    };
+    if iter.next().is_some() {
+        return None;
+    }
+    Some(result)
+}
+
+fn is_const_function(func: &Function) -> bool {
+    matches!(&func.name, FunctionName::Ident(ident) if &ident.value == "const")
+}
+
+/// Parses values as a `calc()` sum, so that they can be used in math
+/// functions.
+fn parse_calc_sum(values: &[ComponentValue], span: Span) -> Option<CalcSum> {
+    let value = format!("calc({})", serialize_component_values(values));
+    match parse_declaration_value("width", &value, span)?.pop()? {
+        ComponentValue::Function(mut func) => match func.value.pop()? {
+            ComponentValue::CalcSum(sum) if func.value.is_empty() => Some(*sum),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl VisitMut for UpdateConstValues<'_> {
+    fn visit_mut_declaration(&mut self, decl: &mut Declaration) {
+        self.substituted = false;
+        self.substitute(&mut decl.value);
+        // const values can be used in nested functions, e.g. `calc()`
+        decl.visit_mut_children_with(self);
+
+        if self.substituted {
+            // values with substituted tokens are parsed again, values that are
+            // using constants can be evaluated at compile time
+            let value = serialize_declaration_value(decl);
+            let value = eval_value(&value).unwrap_or(value);
+            let name = match &decl.name {
+                DeclarationName::Ident(ident) => ident.value.to_string(),
+                DeclarationName::DashedIdent(ident) => format!("--{}", ident.value),
+            };
+            if let Some(value) = parse_declaration_value(&name, &value, decl.span) {
+                decl.value = value;
+            }
+        }
+    }
+
+    fn visit_mut_function(&mut self, func: &mut Function) {
+        self.substitute(&mut func.value);
+        func.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_calc_value(&mut self, value: &mut CalcValue) {
+        if let CalcValue::Function(func) = value {
+            if is_const_function(func) {
+                self.substituted = true;
+                if let Some(values) = self.resolve(func) {
+                    if let Some(sum) = parse_calc_sum(&values, func.span) {
+                        *value = CalcValue::Sum(sum);
+                    } else {
+                        HANDLER.with(|handler| {
+                            handler
+                                .struct_span_err(
+                                    func.span,
+                                    "Invalid const value in a math function, expected a number, \
+                                     dimension or percentage",
+                                )
+                                .emit();
+                        });
                     }
                 }
-                r.push(v.clone());
+                return;
             }
-            decl.value = r;
         }
+        value.visit_mut_children_with(self);
     }
 }
 
 struct ModuleCompiler<'a> {
     scope: ModuleScope<'a>,
     class_maps: Vec<ClassMap>,
+    /// Spans of `@classmap` rules.
+    class_map_spans: Vec<Span>,
+    /// Spans of the first occurrences of local IDs, they are used in
+    /// declaration maps.
+    id_spans: FxHashMap<(IdKind, JsWord), Span>,
     has_keyframes: bool,
 }
 
@@ -719,26 +1137,39 @@ impl<'a> ModuleCompiler<'a> {
         Self {
             scope: ModuleScope::new(css_map, module_id),
             class_maps: Vec::new(),
+            class_map_spans: Vec::new(),
+            id_spans: FxHashMap::default(),
             has_keyframes: false,
         }
     }
+
+    /// Returns [Id] and stores a span of the first occurrence of a local ID.
+    fn get_id(&mut self, kind: IdKind, local_id: &JsWord, span: Span) -> Rc<Id> {
+        let id = self.scope.get_id(kind, local_id);
+        if id.module_index == self.scope.module_index && !span.is_dummy() {
+            self.id_spans
+                .entry((kind, local_id.clone()))
+                .or_insert(span);
+        }
+        id
+    }
 }
 
 impl VisitMut for ModuleCompiler<'_> {
     fn visit_mut_class_selector(&mut self, selector: &mut ClassSelector) {
-        let id = self.scope.get_id(IdKind::Class, &selector.text.value);
+        let id = self.get_id(IdKind::Class, &selector.text.value, selector.span);
         selector.text.value = (&id.global_id[..]).into();
     }
 
     fn visit_mut_dashed_ident(&mut self, ident: &mut DashedIdent) {
-        let id = self.scope.get_id(IdKind::Var, &ident.value);
+        let id = self.get_id(IdKind::Var, &ident.value, ident.span);
         ident.value = (&id.global_id[..]).into();
     }
 
     fn visit_mut_keyframes_name(&mut self, name: &mut KeyframesName) {
         // Ignore `@keyframes "string identifier" {...}`
         if let KeyframesName::CustomIdent(ident) = name {
-            let id = self.scope.get_id(IdKind::Keyframes, &ident.value);
+            let id = self.get_id(IdKind::Keyframes, &ident.value, ident.span);
             ident.value = (&id.global_id[..]).into();
             self.has_keyframes = true;
         }
@@ -753,6 +1184,20 @@ impl VisitMut for ModuleCompiler<'_> {
                     if &at_rule.name == "extern" {
                         match parse_at_extern(at_rule) {
                             Ok(ext) => {
+                                if self.scope.contains(ext.kind, &ext.local_id) {
+                                    HANDLER.with(|handler| {
+                                        handler
+                                            .struct_span_warn(
+                                                at_rule.span,
+                                                &format!(
+                                                    "'{}' is already declared, extern declaration \
+                                                     is ignored",
+                                                    ext.local_id
+                                                ),
+                                            )
+                                            .emit();
+                                    });
+                                }
                                 self.scope.add_extern(
                                     &ext.module_id,
                                     ext.kind,
@@ -772,6 +1217,7 @@ impl VisitMut for ModuleCompiler<'_> {
                                 let states_num = class_map.states.len();
                                 if states_num >= 2 && states_num <= 8 {
                                     self.class_maps.push(class_map);
+                                    self.class_map_spans.push(at_rule.span);
                                 } else {
                                     HANDLER.with(|handler| {
                                         handler
@@ -808,86 +1254,376 @@ impl VisitMut for ModuleCompiler<'_> {
     }
 }
 
+/// TS enum member.
+struct TsMember<'a> {
+    /// Member name.
+    name: String,
+    /// Local name in the module scope.
+    local: &'a JsWord,
+    id: &'a Rc<Id>,
+    /// Span of the local declaration.
+    span: Option<Span>,
+}
+
+/// Converts local names into TS enum member names.
+///
+/// Different local names can be transformed into the same member name, e.g.
+/// `button-primary` and `buttonPrimary`, such collisions are reported as
+/// errors.
+fn ts_members<'a>(
+    handler: &Handler,
+    ids: &[(&'a JsWord, &'a Rc<Id>)],
+    naming: TsNaming,
+    id_spans: &FxHashMap<(IdKind, JsWord), Span>,
+) -> Vec<TsMember<'a>> {
+    let mut names: FxHashMap<String, &JsWord> = FxHashMap::default();
+    let mut result = Vec::with_capacity(ids.len());
+    for &(local, id) in ids {
+        let name = naming.member_name(local);
+        if let Some(prev) = names.insert(name.clone(), local) {
+            handler.err(&format!(
+                "{} names \"{}\" and \"{}\" are mapped to the same TS enum member \"{}\"",
+                id.kind, prev, local, name
+            ));
+        }
+        let span = id_spans.get(&(id.kind, local.clone())).copied();
+        result.push(TsMember {
+            name,
+            local,
+            id,
+            span,
+        });
+    }
+    result
+}
+
 fn emit_id_comment<W: Write>(
     output: &mut W,
-    id: &(&JsWord, &Rc<Id>),
+    member: &TsMember,
     module_index: u32,
     modules: &Vec<Box<CssMapModule>>,
+    doc_comments: &DocComments,
 ) -> Result<(), std::fmt::Error> {
-    if id.1.module_index == module_index {
-        write!(output, "/** {} {{@link {}}} */\n", id.1.kind, id.0)
+    let id = member.id;
+    // local names that aren't used as member names are added to comments
+    if id.module_index == module_index {
+        let doc = doc_comments.get(id.kind, &id.local_id);
+        if member.name == member.local[..] {
+            write_jsdoc(
+                output,
+                "  ",
+                format_args!("{} {{@link {}}}", id.kind, member.name),
+                doc,
+            )
+        } else {
+            write_jsdoc(
+                output,
+                "  ",
+                format_args!("{} {} {{@link {}}}", id.kind, member.local, member.name),
+                doc,
+            )
+        }
     } else {
-        if id.0 == &id.1.local_id[..] {
+        let module_id = &modules[id.module_index as usize].id;
+        if member.local != &id.local_id[..] {
             write!(
                 output,
-                "/** extern {} {{@link {}}} from '{}' */\n",
-                id.1.kind, id.0, modules[id.1.module_index as usize].id
+                "/** extern {} {} as {{@link {}}} from '{}' */\n",
+                id.kind, id.local_id, member.name, module_id
+            )
+        } else if member.name != member.local[..] {
+            write!(
+                output,
+                "/** extern {} {} {{@link {}}} from '{}' */\n",
+                id.kind, member.local, member.name, module_id
             )
         } else {
             write!(
                 output,
-                "/** extern {} {} as {{@link {}}} from '{}' */\n",
-                id.1.kind, id.1.local_id, id.0, modules[id.1.module_index as usize].id
+                "/** extern {} {{@link {}}} from '{}' */\n",
+                id.kind, member.name, module_id
             )
         }
     }
 }
 
-fn emit_ts<W: Write>(
-    output: &mut W,
-    classes: &[(&JsWord, &Rc<Id>)],
-    vars: &[(&JsWord, &Rc<Id>)],
-    keyframes: &[(&JsWord, &Rc<Id>)],
+/// [EmitContext] contains module members and options that are used to emit
+/// JS modules and TS declarations.
+struct EmitContext<'a> {
+    classes: &'a [TsMember<'a>],
+    vars: &'a [TsMember<'a>],
+    keyframes: &'a [TsMember<'a>],
+    /// Theme names in the declaration order.
+    themes: &'a [String],
     module_index: u32,
-    class_maps: &[ClassMap],
-    modules: &Vec<Box<CssMapModule>>,
+    class_maps: &'a [ClassMap],
+    modules: &'a Vec<Box<CssMapModule>>,
+    /// Spans of `@classmap` rules in the same order as `class_maps`.
+    class_map_spans: &'a [Span],
+    /// Doc comments of module IDs.
+    doc_comments: &'a DocComments,
+    id_output: IdOutput,
+    /// Mappings from emitted TS declarations to the module source.
+    declaration_map: DeclarationMap<'a>,
+}
+
+fn emit_ts<W: Write>(
+    output: &mut LineWriter<W>,
+    ctx: &mut EmitContext,
 ) -> Result<(), std::fmt::Error> {
-    write!(output, "/** class names */\n")?;
-    write!(output, "export const enum c {{\n")?;
-    for id in classes {
-        write!(output, "  ")?;
-        emit_id_comment(output, id, module_index, modules)?;
-        write!(output, "  {} = \"{}\",\n", id.0, id.1.global_id)?;
+    let &mut EmitContext {
+        classes,
+        vars,
+        keyframes,
+        themes,
+        module_index,
+        class_maps,
+        modules,
+        class_map_spans,
+        doc_comments,
+        id_output,
+        ref mut declaration_map,
+    } = ctx;
+    let groups = [
+        ("class names", "c", classes),
+        ("vars", "v", vars),
+        ("keyframes", "k", keyframes),
+    ];
+    for (title, name, members) in groups {
+        write!(output, "/** {} */\n", title)?;
+        emit_ts_declaration_start(output, name, id_output)?;
+        for member in members {
+            let start_line = output.line;
+            write!(output, "  ")?;
+            emit_id_comment(output, member, module_index, modules, doc_comments)?;
+            emit_ts_member(output, &member.name, &member.id.global_id, id_output)?;
+            if let Some(span) = member.span {
+                declaration_map.add(start_line..output.line, span);
+            }
+        }
+        emit_ts_declaration_end(output, id_output)?;
     }
-    write!(output, "}}\n")?;
 
-    write!(output, "/** vars */\n")?;
-    write!(output, "export const enum v {{\n")?;
-    for id in vars {
-        write!(output, "  ")?;
-        emit_id_comment(output, id, module_index, modules)?;
-        write!(output, "  {} = \"{}\",\n", id.0, id.1.global_id)?;
+    if !themes.is_empty() {
+        write!(output, "/** themes */\n")?;
+        emit_ts_declaration_start(output, "t", id_output)?;
+        for name in themes {
+            emit_ts_member(output, name, name, id_output)?;
+        }
+        emit_ts_declaration_end(output, id_output)?;
     }
-    write!(output, "}}\n")?;
 
-    write!(output, "/** keyframes */\n")?;
-    write!(output, "export const enum k {{\n")?;
-    for id in keyframes {
-        write!(output, "  ")?;
-        emit_id_comment(output, id, module_index, modules)?;
-        write!(output, "  {} = \"{}\",\n", id.0, id.1.global_id)?;
+    if !vars.is_empty() {
+        emit_ts_var_helpers(output, vars, declaration_map)?;
     }
-    write!(output, "}}\n")?;
 
     if !class_maps.is_empty() {
         output.write_char('\n')?;
-        for cn in class_maps {
-            cn.emit_ts(output)?;
+        for (cn, span) in class_maps.iter().zip(class_map_spans) {
+            let start_line = output.line;
+            cn.emit_ts(
+                output,
+                doc_comments
+                    .class_maps
+                    .get(&cn.name[..])
+                    .map(|doc| &doc[..]),
+            )?;
+            declaration_map.add(start_line..output.line, *span);
         }
     }
 
     Ok(())
 }
 
+/// [DeclarationMap] collects mappings from generated TS declarations to
+/// declarations in a module source.
+///
+/// Spans are positions in the expanded module source. Expansions keep line
+/// breaks, so source lines match the module source, but columns can be
+/// shifted on lines with expanded code.
+struct DeclarationMap<'a> {
+    line_index: LineIndex<'a>,
+    start_pos: BytePos,
+    mappings: Vec<Mapping>,
+}
+
+impl<'a> DeclarationMap<'a> {
+    fn new(source_file: &'a SourceFile) -> Self {
+        Self {
+            line_index: LineIndex::new(&source_file.src),
+            start_pos: source_file.start_pos,
+            mappings: Vec::new(),
+        }
+    }
+
+    /// Maps generated lines to the start of a span.
+    fn add(&mut self, generated_lines: Range<u32>, span: Span) {
+        if span.is_dummy() || span.lo < self.start_pos {
+            return;
+        }
+        let (source_line, source_column) = self
+            .line_index
+            .line_col((span.lo.0 - self.start_pos.0) as usize);
+        for generated_line in generated_lines {
+            self.mappings.push(Mapping {
+                generated_line,
+                generated_column: 0,
+                source_line,
+                source_column,
+            });
+        }
+    }
+}
+
+/// Emits declarations for `varRef`, `Vars` and `setVars()` helpers.
+fn emit_ts_var_helpers<W: Write>(
+    output: &mut LineWriter<W>,
+    vars: &[TsMember],
+    declaration_map: &mut DeclarationMap,
+) -> Result<(), std::fmt::Error> {
+    write!(output, "/** var references, e.g. `var(--a)` */\n")?;
+    write!(output, "export declare const varRef: {{\n")?;
+    for member in vars {
+        let start_line = output.line;
+        write!(output, "  /** var {{@link {}}} */\n", member.name)?;
+        write!(
+            output,
+            "  readonly {}: \"var(--{})\";\n",
+            member.name, member.id.global_id
+        )?;
+        if let Some(span) = member.span {
+            declaration_map.add(start_line..output.line, span);
+        }
+    }
+    write!(output, "}};\n")?;
+
+    write!(output, "/** var values */\n")?;
+    write!(output, "export type Vars = {{\n")?;
+    for member in vars {
+        write!(output, "  {}?: string;\n", member.name)?;
+    }
+    write!(output, "}};\n")?;
+
+    write!(output, "/** Sets var values on a style declaration. */\n")?;
+    write!(
+        output,
+        "export declare function setVars(style: {{ setProperty(property: string, value: string): \
+         void }}, vars: Vars): void;\n"
+    )
+}
+
+/// Emits `varRef` and `setVars()` helpers, helpers are emitted for all ID
+/// output types because const enums don't have runtime values.
+fn emit_js_var_helpers<W: Write>(output: &mut W, vars: &[TsMember]) -> Result<(), std::fmt::Error> {
+    write!(output, "export const varRef = {{\n")?;
+    for member in vars {
+        write!(
+            output,
+            "  {}: \"var(--{})\",\n",
+            member.name, member.id.global_id
+        )?;
+    }
+    write!(output, "}};\n")?;
+
+    write!(output, "const __VARS = {{\n")?;
+    for member in vars {
+        write!(
+            output,
+            "  {}: \"--{}\",\n",
+            member.name, member.id.global_id
+        )?;
+    }
+    write!(output, "}};\n")?;
+
+    write!(output, "export function setVars(style, vars) {{\n")?;
+    write!(output, "  for (const k in vars) {{\n")?;
+    write!(output, "    const name = __VARS[k];\n")?;
+    write!(output, "    const value = vars[k];\n")?;
+    write!(
+        output,
+        "    if (typeof name === \"string\" && value !== void 0) {{\n"
+    )?;
+    write!(output, "      style.setProperty(name, value);\n")?;
+    write!(output, "    }}\n")?;
+    write!(output, "  }}\n")?;
+    write!(output, "}}\n")
+}
+
+fn emit_ts_declaration_start<W: Write>(
+    output: &mut W,
+    name: &str,
+    id_output: IdOutput,
+) -> Result<(), std::fmt::Error> {
+    match id_output {
+        IdOutput::ConstEnum => write!(output, "export const enum {} {{\n", name),
+        IdOutput::Object => write!(output, "export declare const {}: {{\n", name),
+    }
+}
+
+fn emit_ts_member<W: Write>(
+    output: &mut W,
+    name: &str,
+    value: &str,
+    id_output: IdOutput,
+) -> Result<(), std::fmt::Error> {
+    match id_output {
+        IdOutput::ConstEnum => write!(output, "  {} = \"{}\",\n", name, value),
+        IdOutput::Object => write!(output, "  readonly {}: \"{}\";\n", name, value),
+    }
+}
+
+fn emit_ts_declaration_end<W: Write>(
+    output: &mut W,
+    id_output: IdOutput,
+) -> Result<(), std::fmt::Error> {
+    match id_output {
+        IdOutput::ConstEnum => write!(output, "}}\n"),
+        IdOutput::Object => write!(output, "}};\n"),
+    }
+}
+
 fn emit_js<W: Write>(
     output: &mut W,
-    class_maps: &[ClassMap],
+    ctx: &EmitContext,
     class_map_output: ClassMapOutput,
 ) -> Result<(), std::fmt::Error> {
-    if !class_maps.is_empty() {
-        for cm in class_maps {
-            cm.emit_js(output, class_map_output)?;
+    let &EmitContext {
+        classes,
+        vars,
+        keyframes,
+        themes,
+        class_maps,
+        id_output,
+        ..
+    } = ctx;
+    // const enums are inlined by `tsc` and don't have any runtime values
+    if let IdOutput::Object = id_output {
+        for (name, members) in [("c", classes), ("v", vars), ("k", keyframes)] {
+            write!(output, "export const {} = {{\n", name)?;
+            for member in members {
+                write!(output, "  {}: \"{}\",\n", member.name, member.id.global_id)?;
+            }
+            write!(output, "}};\n")?;
         }
+        if !themes.is_empty() {
+            write!(output, "export const t = {{\n")?;
+            for name in themes {
+                write!(output, "  {}: \"{}\",\n", name, name)?;
+            }
+            write!(output, "}};\n")?;
+        }
+    }
+
+    if !vars.is_empty() {
+        emit_js_var_helpers(output, vars)?;
+    }
+
+    if !class_maps.is_empty() && (id_output == IdOutput::Object || !vars.is_empty()) {
+        output.write_char('\n')?;
+    }
+
+    for cm in class_maps {
+        cm.emit_js(output, class_map_output)?;
     }
 
     Ok(())
@@ -901,9 +1637,21 @@ pub struct CompilationArtifact {
     pub css: String,
     pub js: String,
     pub ts: String,
+    /// Mappings from `ts` lines to declarations in the module source.
+    pub ts_mappings: Vec<Mapping>,
+    /// Names of global const values used by the module.
+    pub consts: Vec<JsWord>,
+    /// Names of custom media queries used by the module.
+    pub custom_media: Vec<JsWord>,
+    /// Names of global mixins used by the module.
+    pub mixins: Vec<JsWord>,
+    /// IDs resolved by the module, including extern IDs.
+    pub ids: Vec<Rc<Id>>,
+    /// Warnings reported during compilation.
+    pub diagnostics: Diagnostics,
 }
 
-fn is_valid_js_ident(ident: &str) -> bool {
+pub(crate) fn is_valid_js_ident(ident: &str) -> bool {
     let mut iter = ident.chars();
     if let Some(next) = iter.next() {
         match next {
@@ -922,3 +1670,355 @@ fn is_valid_js_ident(ident: &str) -> bool {
         false
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{config::Config, const_map::extract_const_values};
+
+    /// Compiles a module `a` with an empty css map.
+    pub(crate) fn compile_module(
+        source: &str,
+        consts: &ResolvedConsts,
+        options: &CompilerOptions,
+    ) -> Result<CompilationArtifact, Diagnostics> {
+        let mut css_map = CssMap::new(&vec![], &vec![], &vec![]).unwrap();
+        compile(
+            "a.css",
+            source.to_string(),
+            &mut css_map,
+            consts,
+            "a",
+            options,
+        )
+    }
+
+    /// Compiles a module with default options and without const values.
+    pub(crate) fn compile_css(source: &str) -> Result<CompilationArtifact, Diagnostics> {
+        compile_module(
+            source,
+            &ResolvedConsts::default(),
+            &Config::default().compiler_options(),
+        )
+    }
+
+    /// Resolves const values from a const file source.
+    fn consts(source: &str) -> ResolvedConsts {
+        let file = extract_const_values(Path::new("const.css"), source.to_string()).unwrap();
+        ResolvedConsts {
+            values: file.values,
+            custom_media: file.custom_media,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn prelude_consts() {
+        let consts = consts(":root { --TABLET: 768px; --WIDE: 1280px; }");
+        let options = Config::default().compiler_options();
+        let artifact = compile_module(
+            "@media (min-width: const(--TABLET)) { .a { color: red; } }",
+            &consts,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            artifact.css,
+            "@media (min-width: 768px) {\n  .a {\n    color: red;\n  }\n}"
+        );
+        assert_eq!(artifact.consts, [JsWord::from("TABLET")]);
+
+        let artifact = compile_module(
+            ":const { --TABLET: 1024px; }\n@media (min-width: const(--TABLET)) and (max-width: \
+             const(--WIDE)) { .a { color: red; } }",
+            &consts,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            artifact.css,
+            "@media (min-width: 1024px) and (max-width: 1280px) {\n  .a {\n    color: red;\n  }\n}"
+        );
+        assert_eq!(artifact.consts, [JsWord::from("WIDE")]);
+
+        let err =
+            compile_module("@media (min-width: const(--A)) {}", &consts, &options).unwrap_err();
+        assert_eq!(err.message, "a.css:1:20: Cannot find a const value '--A'\n");
+
+        let err = compile_module(".a {}\n@media (--b) {}", &consts, &options).unwrap_err();
+        assert_eq!(
+            err.message,
+            "a.css:2:8: Cannot find a custom media query '--b'\n"
+        );
+    }
+
+    #[test]
+    fn local_consts() {
+        // extraction, `:const` blocks are removed from CSS
+        let artifact = compile_css(":const { --GAP: 4px; }\n.a { margin: const(--GAP); }").unwrap();
+        assert_eq!(artifact.css, ".a {\n  margin: 4px;\n}");
+        assert!(artifact.consts.is_empty());
+        assert!(!artifact.ts.contains("GAP"));
+
+        // local values are shadowing global values
+        let consts = consts(":root { --GAP: 8px; --COLOR: red; }");
+        let artifact = compile_module(
+            ":const { --GAP: 4px; }\n.a { margin: const(--GAP); color: const(--COLOR); }",
+            &consts,
+            &Config::default().compiler_options(),
+        )
+        .unwrap();
+        assert_eq!(artifact.css, ".a {\n  margin: 4px;\n  color: red;\n}");
+        assert_eq!(artifact.consts, [JsWord::from("COLOR")]);
+
+        // local values can use previously declared local and global values
+        let artifact = compile_module(
+            ":const { --A: const(--GAP); --B: const(--A) 2px; }\n.a { margin: const(--B); }",
+            &consts,
+            &Config::default().compiler_options(),
+        )
+        .unwrap();
+        assert_eq!(artifact.css, ".a {\n  margin: 8px 2px;\n}");
+        assert_eq!(artifact.consts, [JsWord::from("GAP")]);
+
+        let err = compile_css(":const { color: red; }").unwrap_err();
+        assert_eq!(err.errors, 1);
+        assert!(err.message.contains("Invalid const name"));
+        let err = compile_css(":const { --B: const(--A); --A: 1px; }").unwrap_err();
+        assert!(err.message.contains("Cannot find a const value '--A'"));
+    }
+
+    #[test]
+    fn nested_consts() {
+        let consts = consts(":root { --GAP: 4px; --BRAND: #f00; --SUM: 1px + 2px; }");
+        let artifact = compile_module(
+            ":const { --HALF: calc(const(--GAP) / 2); }\n.a { margin: calc(const(--GAP) * 2) \
+             const(--HALF); padding: calc(const(--SUM) * 2) calc(var(--x) + const(--GAP)); color: \
+             color-mix(in srgb, const(--BRAND) 50%, #00f); }",
+            &consts,
+            &Config::default().compiler_options(),
+        )
+        .unwrap();
+        assert_eq!(
+            artifact.css,
+            ".a {\n  margin: 8px 2px;\n  padding: 6px calc(var(--a) + (4px));\n  color: \
+             #800080;\n}"
+        );
+        assert_eq!(
+            artifact.consts,
+            [
+                JsWord::from("BRAND"),
+                JsWord::from("GAP"),
+                JsWord::from("SUM")
+            ]
+        );
+        assert!(!artifact.ts.contains("GAP"));
+        assert!(!artifact.ts.contains("BRAND"));
+        assert!(artifact.ts.contains("x = \"a\""));
+
+        let artifact = compile_module(
+            ".a { margin: const(--GAP) const(--GAP); border: 1px solid const(--BRAND); }",
+            &consts,
+            &Config::default().compiler_options(),
+        )
+        .unwrap();
+        assert_eq!(
+            artifact.css,
+            ".a {\n  margin: 4px 4px;\n  border: 1px solid #f00;\n}"
+        );
+
+        // strings and urls are never evaluated
+        let artifact = compile_module(
+            ".a { content: \"calc(1 + 2)\" const(--GAP); background: url(\"calc(1+2).png\") \
+             const(--BRAND); }",
+            &consts,
+            &Config::default().compiler_options(),
+        )
+        .unwrap();
+        assert_eq!(
+            artifact.css,
+            ".a {\n  content: \"calc(1 + 2)\" 4px;\n  background: url(\"calc(1+2).png\") #f00;\n}"
+        );
+
+        let err = compile_css(".a { width: calc(const(--A) * 2); }").unwrap_err();
+        assert!(err.message.contains("Cannot find a const value '--A'"));
+    }
+
+    #[test]
+    fn source_error_positions() {
+        let err = compile_css(".a {}\n.b { gap: define(--GAP); }").unwrap_err();
+        assert_eq!(
+            err.message,
+            "a.css:2:11: Cannot find a define value '--GAP'\n"
+        );
+        assert_eq!(err.errors, 1);
+        let err = compile_css(".a {}\n  @each n in 1, 2 {}").unwrap_err();
+        assert!(err.message.starts_with("a.css:2:3: "));
+    }
+
+    #[test]
+    fn object_output() {
+        let mut options = Config::default().compiler_options();
+        options.id_output = IdOutput::Object;
+        let artifact = compile_module(
+            ".button-primary { color: red; }\n@keyframes fade {}\n.a { animation: fade 1s; }",
+            &ResolvedConsts::default(),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            artifact.js,
+            r#"export const c = {
+  a: "b",
+  buttonPrimary: "a",
+};
+export const v = {
+};
+export const k = {
+  fade: "a",
+};
+"#
+        );
+        assert_eq!(
+            artifact.ts,
+            r#"/** class names */
+export declare const c: {
+  /** class {@link a} */
+  readonly a: "b";
+  /** class button-primary {@link buttonPrimary} */
+  readonly buttonPrimary: "a";
+};
+/** vars */
+export declare const v: {
+};
+/** keyframes */
+export declare const k: {
+  /** keyframes {@link fade} */
+  readonly fade: "a";
+};
+"#
+        );
+    }
+
+    #[test]
+    fn declaration_map_lines() {
+        let artifact = compile_css(
+            "@if flag(A) {\n  .A {}\n}\n@each $n in 1, 2 {\n  .B#{$n} {}\n}\n@theme dark { --C: \
+             red; }\n.D {}",
+        )
+        .unwrap();
+        // source line of a generated enum member
+        let source_line = |member: &str| {
+            let line = artifact
+                .ts
+                .lines()
+                .position(|line| line.starts_with(&format!("  {} = ", member)))
+                .unwrap() as u32;
+            artifact
+                .ts_mappings
+                .iter()
+                .find(|m| m.generated_line == line)
+                .map(|m| m.source_line)
+        };
+        assert_eq!(source_line("B1"), Some(4));
+        assert_eq!(source_line("B2"), Some(5));
+        assert_eq!(source_line("C"), Some(6));
+        assert_eq!(source_line("D"), Some(7));
+    }
+
+    const VAR_HELPERS_JS: &str = r#"export const varRef = {
+  Color: "var(--b)",
+  size: "var(--a)",
+};
+const __VARS = {
+  Color: "--b",
+  size: "--a",
+};
+export function setVars(style, vars) {
+  for (const k in vars) {
+    const name = __VARS[k];
+    const value = vars[k];
+    if (typeof name === "string" && value !== void 0) {
+      style.setProperty(name, value);
+    }
+  }
+}
+"#;
+
+    const VAR_HELPERS_TS: &str = r#"/** var references, e.g. `var(--a)` */
+export declare const varRef: {
+  /** var {@link Color} */
+  readonly Color: "var(--b)";
+  /** var {@link size} */
+  readonly size: "var(--a)";
+};
+/** var values */
+export type Vars = {
+  Color?: string;
+  size?: string;
+};
+/** Sets var values on a style declaration. */
+export declare function setVars(style: { setProperty(property: string, value: string): void }, vars: Vars): void;
+"#;
+
+    #[test]
+    fn var_helpers() {
+        let source = ".a { --size: 1px; --Color: red; }";
+        let mut options = Config::default().compiler_options();
+        let artifact = compile_module(source, &ResolvedConsts::default(), &options).unwrap();
+        // const enums are inlined, `.js` file contains only runtime helpers
+        assert_eq!(artifact.js, VAR_HELPERS_JS);
+        assert_eq!(
+            artifact.ts,
+            format!(
+                r#"/** class names */
+export const enum c {{
+  /** class {{@link a}} */
+  a = "a",
+}}
+/** vars */
+export const enum v {{
+  /** var {{@link Color}} */
+  Color = "b",
+  /** var {{@link size}} */
+  size = "a",
+}}
+/** keyframes */
+export const enum k {{
+}}
+{}"#,
+                VAR_HELPERS_TS
+            )
+        );
+
+        options.id_output = IdOutput::Object;
+        let artifact = compile_module(source, &ResolvedConsts::default(), &options).unwrap();
+        assert_eq!(
+            artifact.js,
+            format!(
+                r#"export const c = {{
+  a: "a",
+}};
+export const v = {{
+  Color: "b",
+  size: "a",
+}};
+export const k = {{
+}};
+{}"#,
+                VAR_HELPERS_JS
+            )
+        );
+        assert!(artifact.ts.ends_with(VAR_HELPERS_TS));
+
+        // helpers are emitted only for modules with vars
+        for id_output in [IdOutput::ConstEnum, IdOutput::Object] {
+            options.id_output = id_output;
+            let artifact =
+                compile_module(".a { color: red; }", &ResolvedConsts::default(), &options).unwrap();
+            assert!(!artifact.js.contains("varRef"));
+            assert!(!artifact.ts.contains("varRef"));
+            assert!(!artifact.ts.contains("Vars"));
+        }
+    }
+}
@@ -0,0 +1,346 @@
+use std::{collections::BTreeMap, ops::Range};
+
+use crate::preprocess::{find_block_end, skip_comment, skip_string, SourceError};
+
+/// [Conditionals] resolves `@if` blocks and `define()` values before a
+/// module is parsed, so that IDs from disabled branches are never
+/// allocated.
+///
+/// Removed code is replaced with line breaks, so that line numbers in
+/// diagnostics aren't changed.
+pub struct Conditionals<'a> {
+    defines: &'a BTreeMap<String, String>,
+    /// Offset of a currently expanded block in the source.
+    offset: usize,
+    pub errors: Vec<SourceError>,
+}
+
+impl<'a> Conditionals<'a> {
+    pub fn new(defines: &'a BTreeMap<String, String>) -> Self {
+        Self {
+            defines,
+            offset: 0,
+            errors: Vec::new(),
+        }
+    }
+
+    /// Resolves conditional blocks and defines, returns `None` when source
+    /// doesn't have any conditional blocks or defines.
+    pub fn expand(&mut self, source: &str) -> Option<String> {
+        let result = self.expand_source(source);
+        if result == source {
+            None
+        } else {
+            Some(result)
+        }
+    }
+
+    fn expand_source(&mut self, source: &str) -> String {
+        let bytes = source.as_bytes();
+        let mut result = String::with_capacity(source.len());
+        let mut last = 0;
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                    i = skip_comment(source, i);
+                }
+                b'"' | b'\'' => {
+                    i = skip_string(bytes, i);
+                }
+                b'@' if is_keyword(&source[i..], "@if") => {
+                    if let Some((end, expanded)) = self.expand_if(source, i) {
+                        result.push_str(&source[last..i]);
+                        result.push_str(&expanded);
+                        last = end;
+                        i = end;
+                    } else {
+                        i += 1;
+                    }
+                }
+                b'd' if is_define_call(source, i) => {
+                    let args_start = i + "define(".len();
+                    let args_end = source[args_start..]
+                        .find(')')
+                        .map_or(source.len(), |end| args_start + end);
+                    let name = source[args_start..args_end].trim();
+                    match name.strip_prefix("--").and_then(|n| self.defines.get(n)) {
+                        Some(value) => {
+                            result.push_str(&source[last..i]);
+                            result.push_str(value);
+                            last = (args_end + 1).min(source.len());
+                            i = last;
+                        }
+                        None => {
+                            self.error(i, format!("Cannot find a define value '{}'", name));
+                            i = args_end;
+                        }
+                    }
+                }
+                _ => i += 1,
+            }
+        }
+        result.push_str(&source[last..]);
+        result
+    }
+
+    /// Expands an `@if` chain that starts at `start`, returns an index after
+    /// the end of the chain and an expanded source.
+    fn expand_if(&mut self, source: &str, start: usize) -> Option<(usize, String)> {
+        let mut result = String::new();
+        let mut cursor = start;
+        let mut pos = start + "@if".len();
+        let mut selected = false;
+        loop {
+            let block_start = self.find_block_start(source, pos)?;
+            let enabled = self.eval_condition(source, pos, block_start)?;
+            let block_end = self.find_block_end(source, block_start)?;
+            result.push_str(&line_breaks(&source[cursor..block_start + 1]));
+            self.push_block(
+                &mut result,
+                source,
+                block_start + 1..block_end,
+                enabled && !selected,
+            );
+            selected |= enabled;
+            cursor = block_end + 1;
+
+            let else_start = skip_whitespace(source, cursor);
+            if !is_keyword(&source[else_start..], "@else") {
+                return Some((cursor, result));
+            }
+            let next = skip_whitespace(source, else_start + "@else".len());
+            if is_keyword(&source[next..], "if") {
+                pos = next + "if".len();
+                continue;
+            }
+            if source.as_bytes().get(next) != Some(&b'{') {
+                self.error(else_start, "Expected a block after @else".to_string());
+                return None;
+            }
+            let block_end = self.find_block_end(source, next)?;
+            result.push_str(&line_breaks(&source[cursor..next + 1]));
+            self.push_block(&mut result, source, next + 1..block_end, !selected);
+            return Some((block_end + 1, result));
+        }
+    }
+
+    fn push_block(
+        &mut self,
+        result: &mut String,
+        source: &str,
+        block: Range<usize>,
+        enabled: bool,
+    ) {
+        if enabled {
+            let offset = self.offset;
+            self.offset += block.start;
+            let expanded = self.expand_source(&source[block]);
+            self.offset = offset;
+            result.push_str(&expanded);
+        } else {
+            result.push_str(&line_breaks(&source[block]));
+        }
+    }
+
+    /// Evaluates `flag(NAME)` and `not flag(NAME)` conditions between `start`
+    /// and `end`.
+    fn eval_condition(&mut self, source: &str, start: usize, end: usize) -> Option<bool> {
+        let start = start + source[start..end].len() - source[start..end].trim_start().len();
+        let condition = source[start..end].trim_end();
+        let (negate, flag) = match condition.strip_prefix("not") {
+            Some(rest) if rest.starts_with(char::is_whitespace) => (true, rest.trim_start()),
+            _ => (false, condition),
+        };
+        if let Some(name) = flag
+            .strip_prefix("flag(")
+            .and_then(|flag| flag.strip_suffix(')'))
+        {
+            let name = name.trim();
+            if !name.is_empty() && name.bytes().all(is_ident_byte) {
+                return Some(self.is_enabled(name) != negate);
+            }
+        }
+        self.error(
+            start,
+            format!(
+                "Invalid @if condition '{}', condition should be 'flag(NAME)' or 'not flag(NAME)'",
+                condition
+            ),
+        );
+        None
+    }
+
+    /// Flags are enabled when they are defined with any value except `false`
+    /// and `0`.
+    fn is_enabled(&self, name: &str) -> bool {
+        self.defines
+            .get(name)
+            .is_some_and(|value| !matches!(value.as_str(), "" | "0" | "false"))
+    }
+
+    /// Returns an index of a block that follows a condition.
+    fn find_block_start(&mut self, source: &str, start: usize) -> Option<usize> {
+        let bytes = source.as_bytes();
+        let mut i = start;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'{' => return Some(i),
+                b';' | b'}' => break,
+                b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                    i = skip_comment(source, i);
+                    continue;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        self.error(i, "Expected a block after @if condition".to_string());
+        None
+    }
+
+    /// Returns an index of a closing brace of a block.
+    fn find_block_end(&mut self, source: &str, start: usize) -> Option<usize> {
+        let end = find_block_end(source, start);
+        if end.is_none() {
+            self.error(start, "@if block isn't closed".to_string());
+        }
+        end
+    }
+
+    fn error(&mut self, offset: usize, message: String) {
+        self.errors
+            .push(SourceError::new(self.offset + offset, message));
+    }
+}
+
+pub(crate) fn is_ident_byte(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || c == b'-' || c == b'_'
+}
+
+pub(crate) fn is_keyword(s: &str, keyword: &str) -> bool {
+    s.starts_with(keyword)
+        && !s
+            .as_bytes()
+            .get(keyword.len())
+            .is_some_and(|c| is_ident_byte(*c))
+}
+
+fn is_define_call(source: &str, i: usize) -> bool {
+    source[i..].starts_with("define(") && (i == 0 || !is_ident_byte(source.as_bytes()[i - 1]))
+}
+
+/// Returns an index after whitespace and comments.
+fn skip_whitespace(source: &str, start: usize) -> usize {
+    let bytes = source.as_bytes();
+    let mut i = start;
+    while i < bytes.len() {
+        if bytes[i].is_ascii_whitespace() {
+            i += 1;
+        } else if bytes[i] == b'/' && bytes.get(i + 1) == Some(&b'*') {
+            i = skip_comment(source, i);
+        } else {
+            break;
+        }
+    }
+    i
+}
+
+fn line_breaks(s: &str) -> String {
+    "\n".repeat(s.matches('\n').count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(source: &str) -> (Option<String>, Vec<SourceError>) {
+        let mut defines = BTreeMap::new();
+        defines.insert("grid".to_string(), "true".to_string());
+        defines.insert("legacy".to_string(), "false".to_string());
+        defines.insert("GAP".to_string(), "8px".to_string());
+        let mut conditionals = Conditionals::new(&defines);
+        let result = conditionals.expand(source);
+        (result, conditionals.errors)
+    }
+
+    #[test]
+    fn if_else() {
+        assert_eq!(
+            expand("@if flag(grid) { .a {} } @else { .b {} }")
+                .0
+                .unwrap(),
+            " .a {} "
+        );
+        assert_eq!(
+            expand("@if flag(legacy) { .a {} } @else { .b {} }")
+                .0
+                .unwrap(),
+            " .b {} "
+        );
+        assert_eq!(
+            expand("@if flag(unknown) { .a {} }\n.c {}").0.unwrap(),
+            "\n.c {}"
+        );
+        assert_eq!(
+            expand("@if not flag(legacy) {\n.a {}\n}").0.unwrap(),
+            "\n.a {}\n"
+        );
+    }
+
+    #[test]
+    fn else_if() {
+        assert_eq!(
+            expand("@if flag(legacy) { .a {} } @else if flag(grid) { .b {} } @else { .c {} }")
+                .0
+                .unwrap(),
+            " .b {} "
+        );
+    }
+
+    #[test]
+    fn nested() {
+        assert_eq!(
+            expand("@if flag(grid) { @if flag(legacy) { .a {} } .b {} }")
+                .0
+                .unwrap(),
+            "  .b {} "
+        );
+    }
+
+    #[test]
+    fn preserves_line_numbers() {
+        let source = "@if flag(legacy) {\n.a {}\n}\n@else {\n.b {}\n}\n.c {}";
+        let result = expand(source).0.unwrap();
+        assert_eq!(result.matches('\n').count(), source.matches('\n').count());
+        assert!(result.ends_with(".c {}"));
+    }
+
+    #[test]
+    fn defines() {
+        assert_eq!(
+            expand(".a { gap: define(--GAP); }").0.unwrap(),
+            ".a { gap: 8px; }"
+        );
+        assert_eq!(expand(".a { content: 'define(--GAP)'; }").0, None);
+        let (_, errors) = expand(".a { gap: define(--MISSING); }");
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn invalid_conditions() {
+        assert_eq!(expand("@if grid { .a {} }").1.len(), 1);
+        assert_eq!(expand("@if flag(grid);").1.len(), 1);
+        assert_eq!(expand("@if flag(grid) { .a {}").1.len(), 1);
+    }
+
+    #[test]
+    fn error_offsets() {
+        let offsets =
+            |source| -> Vec<usize> { expand(source).1.iter().map(|err| err.offset).collect() };
+        assert_eq!(offsets(".a {}\n@if  grid { .a {} }"), [11]);
+        assert_eq!(offsets("@if flag(grid) {\n  @if flag(a);\n}"), [30]);
+        assert_eq!(offsets("@if flag(grid) { .a { gap: define(--B); } }"), [27]);
+        assert_eq!(offsets(".a {}\n@if flag(grid) { .a {}"), [21]);
+    }
+}
@@ -1,47 +1,290 @@
 use std::{
+    collections::BTreeMap,
     fs,
     path::{Path, PathBuf},
 };
 
 use color_eyre::eyre::{self, WrapErr};
-use serde::Deserialize;
+use schemars::JsonSchema;
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 
-#[derive(Debug, Deserialize)]
+use crate::{
+    class_map::ClassMapOutput,
+    compiler::{CompilerOptions, IdOutput},
+    naming::TsNaming,
+};
+
+/// xiss config.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
 pub struct Config {
+    /// Path to a JSON Schema, it is used by editors for completion.
+    #[serde(rename = "$schema", default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    /// Output directory.
     #[serde(default = "default_output_path")]
     pub output: PathBuf,
-    #[serde(default = "default_include_path")]
-    pub include: PathBuf,
+    /// Directory with xiss modules or a list of include roots.
+    #[serde(default = "default_include", deserialize_with = "deserialize_include")]
+    #[schemars(with = "ConfigInclude")]
+    pub include: Vec<ConfigIncludeRoot>,
+    /// CSS map options.
     #[serde(default)]
     pub map: ConfigMap,
+    /// Path to a build manifest.
+    #[serde(default = "default_manifest_path")]
+    pub manifest: PathBuf,
+    /// Class map output type.
+    #[serde(default)]
+    pub class_map: ClassMapOutput,
+    /// Naming transform for TS enum members, local IDs like `button-primary`
+    /// aren't valid JS identifiers.
+    #[serde(default)]
+    pub ts_naming: TsNaming,
+    /// ID output type, `object` output works with tools that compile files
+    /// in isolation and can't inline const enums (esbuild, swc, Babel).
+    #[serde(default)]
+    pub id_output: IdOutput,
+    /// Emit `.d.ts.map` declaration maps, go to definition in editors opens
+    /// module sources instead of generated declarations.
+    #[serde(default)]
+    pub declaration_map: bool,
+    /// Minify CSS output.
+    #[serde(default)]
+    pub minify: bool,
+    /// Emit `const.json` files with const values.
+    #[serde(default)]
+    pub const_json: bool,
+    /// Design token files in the W3C Design Tokens format, tokens are used
+    /// as const values by all modules.
+    #[serde(default)]
+    pub tokens: Vec<PathBuf>,
+    /// Build variants with const overlays, modules are compiled into a
+    /// separate CSS file for each variant, e.g. `buttons.brandA.css`.
+    #[serde(default)]
+    pub variants: BTreeMap<String, ConfigVariant>,
+    /// Values for `flag()` conditions in `@if` blocks and `define()`
+    /// functions.
+    #[serde(default)]
+    pub defines: BTreeMap<String, String>,
 }
 
 impl Config {
-    pub fn from_file<P: AsRef<Path>>(path: P) -> eyre::Result<Config> {
-        let config = fs::read_to_string(path.as_ref())
-            .wrap_err_with(|| format!("Failed to load config from '{:?}'", path.as_ref()))?;
-        let config = serde_json::from_str(&config)
-            .wrap_err_with(|| format!("Failed to deserialize config file '{:?}'", path.as_ref()))?;
+    /// Loads config from a JSON or TOML file.
+    ///
+    /// Configs can extend other configs with an `extends` property, paths to
+    /// extended configs are relative to the config file.
+    ///
+    /// Profiles are declared in a `profiles` property, the selected profile
+    /// overrides config properties.
+    pub fn from_file<P: AsRef<Path>>(path: P, profile: Option<&str>) -> eyre::Result<Config> {
+        let value = load_config_value(path.as_ref(), &mut Vec::new())?;
+        Config::from_value_with_profile(value, profile)
+            .wrap_err_with(|| format!("Invalid config file {:?}", path.as_ref()))
+    }
+
+    /// Deserializes config from a JSON value and applies a profile.
+    pub fn from_value_with_profile(
+        mut value: Value,
+        profile: Option<&str>,
+    ) -> eyre::Result<Config> {
+        let profiles = if let Value::Object(object) = &mut value {
+            object.remove("profiles")
+        } else {
+            None
+        };
+        let profiles = match profiles {
+            Some(Value::Object(profiles)) => profiles,
+            Some(_) => return Err(eyre::eyre!("profiles: expected an object")),
+            None => serde_json::Map::new(),
+        };
+
+        // all profiles are validated, so that errors in profiles that aren't
+        // used in local builds are reported
+        let mut result = None;
+        for (name, profile_value) in profiles.iter() {
+            if !profile_value.is_object() {
+                return Err(eyre::eyre!("profiles.{}: expected an object", name));
+            }
+            let mut profile_config = value.clone();
+            merge_values(&mut profile_config, profile_value.clone());
+            let config = Config::from_value(profile_config)
+                .wrap_err_with(|| format!("Invalid profile \"{}\"", name))?;
+            if Some(name.as_str()) == profile {
+                result = Some(config);
+            }
+        }
+
+        if let Some(profile) = profile {
+            if let Some(config) = result {
+                Ok(config)
+            } else {
+                let names: Vec<&str> = profiles.keys().map(|k| k.as_str()).collect();
+                Err(eyre::eyre!(
+                    "Unknown profile \"{}\", available profiles: [{}]",
+                    profile,
+                    names.join(", ")
+                ))
+            }
+        } else {
+            Config::from_value(value)
+        }
+    }
+
+    /// Deserializes config from a JSON value without profiles.
+    pub fn from_value(value: Value) -> eyre::Result<Config> {
+        let config: Config = serde_path_to_error::deserialize(value).map_err(|err| {
+            if err.path().iter().next().is_none() {
+                eyre::eyre!("{}", err.inner())
+            } else {
+                eyre::eyre!("{}: {}", err.path(), err.inner())
+            }
+        })?;
+        // variant names are used in output file names
+        for name in config.variants.keys() {
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            {
+                return Err(eyre::eyre!(
+                    "variants.{}: variant name should contain only alphanumeric characters, '-' \
+                     and '_'",
+                    name
+                ));
+            }
+        }
         Ok(config)
     }
+
+    /// Returns JSON Schema for config files.
+    pub fn json_schema() -> String {
+        let mut schema = serde_json::to_value(schemars::schema_for!(Config))
+            .expect("Failed to serialize config schema");
+        // `extends` is resolved before deserialization
+        schema["properties"]["extends"] = serde_json::json!({
+            "description": "Path to an extended config or a list of paths, paths are relative to the config file.",
+            "anyOf": [
+                { "type": "string" },
+                { "type": "array", "items": { "type": "string" } }
+            ]
+        });
+        // profiles are merged with a config before deserialization
+        schema["properties"]["profiles"] = serde_json::json!({
+            "description": "Named profiles that override config properties, profiles are selected with `--profile NAME`.",
+            "type": "object",
+            "additionalProperties": { "$ref": "#" }
+        });
+        serde_json::to_string_pretty(&schema).expect("Failed to serialize config schema")
+    }
+
+    /// Returns compiler options.
+    pub fn compiler_options(&self) -> CompilerOptions {
+        CompilerOptions {
+            class_map: self.class_map,
+            minify: self.minify,
+            defines: self.defines.clone(),
+            ts_naming: self.ts_naming,
+            id_output: self.id_output,
+            declaration_map: self.declaration_map,
+        }
+    }
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
+            schema: None,
             output: "build/css/".into(),
-            include: "css".into(),
+            include: default_include(),
             map: ConfigMap::default(),
+            manifest: default_manifest_path(),
+            class_map: ClassMapOutput::default(),
+            ts_naming: TsNaming::default(),
+            id_output: IdOutput::default(),
+            declaration_map: false,
+            minify: false,
+            const_json: false,
+            tokens: Vec::new(),
+            variants: BTreeMap::new(),
+            defines: BTreeMap::new(),
         }
     }
 }
 
-#[derive(Debug, Deserialize)]
+/// Build variant.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigVariant {
+    /// Const files and design token files with values that override const
+    /// values in all modules.
+    #[serde(default)]
+    pub consts: Vec<PathBuf>,
+}
+
+/// Include root entry.
+///
+/// Module IDs are relative paths to the root directory with an optional
+/// prefix, e.g. `ui/buttons`.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigIncludeRoot {
+    /// Directory with xiss modules.
+    pub path: PathBuf,
+    /// Module ID prefix.
+    #[serde(default)]
+    pub prefix: Option<String>,
+    /// Output directory, when it isn't specified modules are stored in the
+    /// shared output directory.
+    #[serde(default)]
+    pub output: Option<PathBuf>,
+}
+
+/// Include roots can be declared as a path, an entry or a list of entries.
+#[allow(dead_code)]
+#[derive(JsonSchema)]
+#[serde(untagged)]
+enum ConfigInclude {
+    Path(PathBuf),
+    Root(ConfigIncludeRoot),
+    Roots(Vec<ConfigIncludeRoot>),
+}
+
+/// Deserializes include roots.
+///
+/// Untagged enums are hiding errors from nested values, so include roots are
+/// deserialized from JSON values.
+fn deserialize_include<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<ConfigIncludeRoot>, D::Error> {
+    match Value::deserialize(deserializer)? {
+        Value::String(path) => Ok(vec![ConfigIncludeRoot {
+            path: path.into(),
+            prefix: None,
+            output: None,
+        }]),
+        value @ Value::Object(_) => serde_json::from_value(value)
+            .map(|root| vec![root])
+            .map_err(D::Error::custom),
+        value @ Value::Array(_) => serde_json::from_value(value).map_err(D::Error::custom),
+        _ => Err(D::Error::custom(
+            "expected a path, an include root or a list of include roots",
+        )),
+    }
+}
+
+/// CSS map options.
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct ConfigMap {
+    /// Path to a CSS map file.
     #[serde(default = "default_config_map_path")]
     pub path: PathBuf,
+    /// Path to a CSS map lock file.
     #[serde(default = "default_config_map_lock_path")]
     pub lock: PathBuf,
+    /// Regular expressions for global IDs that shouldn't be generated.
     #[serde(default)]
     pub exclude: ConfigMapExclude,
 }
@@ -56,7 +299,8 @@ impl Default for ConfigMap {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
 pub struct ConfigMapExclude {
     #[serde(default)]
     pub class: Vec<String>,
@@ -76,12 +320,160 @@ impl Default for ConfigMapExclude {
     }
 }
 
+/// Loads config file as a JSON value and merges it with extended configs.
+fn load_config_value(path: &Path, stack: &mut Vec<PathBuf>) -> eyre::Result<Value> {
+    let canonical_path = path
+        .canonicalize()
+        .wrap_err_with(|| format!("Failed to load config from {:?}", path))?;
+    if stack.contains(&canonical_path) {
+        return Err(eyre::eyre!("Circular config extends {:?}", path));
+    }
+
+    let contents = fs::read_to_string(path)
+        .wrap_err_with(|| format!("Failed to load config from {:?}", path))?;
+    let mut value: Value = if path.extension().is_some_and(|ext| ext == "toml") {
+        toml::from_str(&contents)
+            .wrap_err_with(|| format!("Failed to parse config file {:?}", path))?
+    } else {
+        serde_json::from_str(&contents)
+            .wrap_err_with(|| format!("Failed to parse config file {:?}", path))?
+    };
+    let object = if let Value::Object(object) = &mut value {
+        object
+    } else {
+        return Err(eyre::eyre!(
+            "Invalid config file {:?}, expected an object",
+            path
+        ));
+    };
+
+    // paths in extended configs are relative to the extended config file
+    if !stack.is_empty() {
+        if let Some(dir) = path.parent() {
+            rebase_paths(object, dir);
+        }
+    }
+
+    if let Some(extends) = object.remove("extends") {
+        let extends = match extends {
+            Value::String(s) => vec![s],
+            Value::Array(items) => {
+                let mut result = Vec::with_capacity(items.len());
+                for item in items {
+                    if let Value::String(s) = item {
+                        result.push(s);
+                    } else {
+                        return Err(eyre::eyre!(
+                            "Invalid config file {:?}, extends should be a path or a list of paths",
+                            path
+                        ));
+                    }
+                }
+                result
+            }
+            _ => {
+                return Err(eyre::eyre!(
+                    "Invalid config file {:?}, extends should be a path or a list of paths",
+                    path
+                ))
+            }
+        };
+
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        stack.push(canonical_path);
+        let mut base = Value::Object(serde_json::Map::new());
+        for extended_path in extends {
+            merge_values(
+                &mut base,
+                load_config_value(&dir.join(extended_path), stack)?,
+            );
+        }
+        stack.pop();
+        merge_values(&mut base, value);
+        value = base;
+    }
+
+    Ok(value)
+}
+
+/// Rebases relative paths in a config object onto a directory.
+fn rebase_paths(object: &mut serde_json::Map<String, Value>, dir: &Path) {
+    rebase_path(object.get_mut("output"), dir);
+    rebase_path(object.get_mut("manifest"), dir);
+    match object.get_mut("include") {
+        Some(Value::Array(roots)) => {
+            for root in roots {
+                rebase_include_root(root, dir);
+            }
+        }
+        Some(value @ Value::Object(_)) => rebase_include_root(value, dir),
+        value => rebase_path(value, dir),
+    }
+    if let Some(Value::Object(map)) = object.get_mut("map") {
+        rebase_path(map.get_mut("path"), dir);
+        rebase_path(map.get_mut("lock"), dir);
+    }
+    rebase_path_list(object.get_mut("tokens"), dir);
+    if let Some(Value::Object(variants)) = object.get_mut("variants") {
+        for variant in variants.values_mut() {
+            if let Value::Object(variant) = variant {
+                rebase_path_list(variant.get_mut("consts"), dir);
+            }
+        }
+    }
+    if let Some(Value::Object(profiles)) = object.get_mut("profiles") {
+        for profile in profiles.values_mut() {
+            if let Value::Object(profile) = profile {
+                rebase_paths(profile, dir);
+            }
+        }
+    }
+}
+
+fn rebase_include_root(root: &mut Value, dir: &Path) {
+    if let Value::Object(root) = root {
+        rebase_path(root.get_mut("path"), dir);
+        rebase_path(root.get_mut("output"), dir);
+    }
+}
+
+fn rebase_path_list(value: Option<&mut Value>, dir: &Path) {
+    if let Some(Value::Array(paths)) = value {
+        for path in paths {
+            rebase_path(Some(path), dir);
+        }
+    }
+}
+
+/// Absolute paths are preserved.
+fn rebase_path(value: Option<&mut Value>, dir: &Path) {
+    if let Some(Value::String(path)) = value {
+        *path = dir.join(&*path).to_string_lossy().into_owned();
+    }
+}
+
+/// Deeply merges objects, all other values are replaced.
+fn merge_values(base: &mut Value, value: Value) {
+    match (base, value) {
+        (Value::Object(base), Value::Object(value)) => {
+            for (k, v) in value {
+                merge_values(base.entry(k).or_insert(Value::Null), v);
+            }
+        }
+        (base, value) => *base = value,
+    }
+}
+
 fn default_output_path() -> PathBuf {
     "build/css/".into()
 }
 
-fn default_include_path() -> PathBuf {
-    "css/".into()
+fn default_include() -> Vec<ConfigIncludeRoot> {
+    vec![ConfigIncludeRoot {
+        path: "css/".into(),
+        prefix: None,
+        output: None,
+    }]
 }
 
 fn default_config_map_path() -> PathBuf {
@@ -91,3 +483,179 @@ fn default_config_map_path() -> PathBuf {
 fn default_config_map_lock_path() -> PathBuf {
     "xiss-map.lock.csv".into()
 }
+
+fn default_manifest_path() -> PathBuf {
+    "xiss-manifest.json".into()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn merge_objects() {
+        let mut base = json!({ "output": "a", "map": { "path": "a.csv", "lock": "a.lock.csv" } });
+        merge_values(&mut base, json!({ "map": { "path": "b.csv" } }));
+        assert_eq!(
+            base,
+            json!({ "output": "a", "map": { "path": "b.csv", "lock": "a.lock.csv" } })
+        );
+    }
+
+    #[test]
+    fn merge_replaces_arrays() {
+        let mut base = json!({ "include": ["a", "b"] });
+        merge_values(&mut base, json!({ "include": ["c"] }));
+        assert_eq!(base, json!({ "include": ["c"] }));
+    }
+
+    #[test]
+    fn include_path() {
+        let config = Config::from_value(json!({ "include": "styles" })).unwrap();
+        assert_eq!(config.include.len(), 1);
+        assert_eq!(config.include[0].path, Path::new("styles"));
+    }
+
+    #[test]
+    fn include_roots() {
+        let config = Config::from_value(json!({
+            "include": [
+                { "path": "ui", "prefix": "ui" },
+                { "path": "admin", "prefix": "admin", "output": "build/admin" },
+            ]
+        }))
+        .unwrap();
+        assert_eq!(config.include.len(), 2);
+        assert_eq!(config.include[1].prefix.as_deref(), Some("admin"));
+    }
+
+    #[test]
+    fn unknown_field() {
+        let err = Config::from_value(json!({ "map": { "exlude": {} } })).unwrap_err();
+        let message = err.to_string();
+        assert!(
+            message.starts_with("map.exlude: unknown field `exlude`"),
+            "{}",
+            message
+        );
+    }
+
+    #[test]
+    fn unknown_include_root_field() {
+        let err =
+            Config::from_value(json!({ "include": [{ "path": "a", "prefx": "a" }] })).unwrap_err();
+        assert!(err.to_string().contains("unknown field `prefx`"));
+    }
+
+    #[test]
+    fn profile() {
+        let value = json!({
+            "minify": false,
+            "map": { "path": "a.csv" },
+            "profiles": {
+                "prod": { "minify": true, "classMap": "table" },
+            },
+        });
+        let config = Config::from_value_with_profile(value.clone(), None).unwrap();
+        assert!(!config.minify);
+        let config = Config::from_value_with_profile(value, Some("prod")).unwrap();
+        assert!(config.minify);
+        assert_eq!(config.class_map, ClassMapOutput::Table);
+        assert_eq!(config.map.path, Path::new("a.csv"));
+    }
+
+    #[test]
+    fn ts_naming() {
+        let config = Config::from_value(json!({})).unwrap();
+        assert_eq!(config.ts_naming, TsNaming::CamelCase);
+        let config = Config::from_value(json!({ "tsNaming": "PascalCase" })).unwrap();
+        assert_eq!(config.ts_naming, TsNaming::PascalCase);
+        assert!(Config::from_value(json!({ "tsNaming": "snake_case" })).is_err());
+    }
+
+    #[test]
+    fn id_output() {
+        let config = Config::from_value(json!({})).unwrap();
+        assert_eq!(config.id_output, IdOutput::ConstEnum);
+        let config = Config::from_value(json!({ "idOutput": "object" })).unwrap();
+        assert_eq!(config.id_output, IdOutput::Object);
+    }
+
+    #[test]
+    fn unknown_profile() {
+        let value = json!({ "profiles": { "prod": {} } });
+        let err = Config::from_value_with_profile(value, Some("dev")).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unknown profile \"dev\", available profiles: [prod]"
+        );
+    }
+
+    #[test]
+    fn invalid_profile() {
+        let value = json!({ "profiles": { "prod": { "minfy": true } } });
+        let err = Config::from_value_with_profile(value, None).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid profile \"prod\"");
+    }
+
+    #[test]
+    fn variants() {
+        let config = Config::from_value(json!({
+            "variants": { "brandA": { "consts": ["brands/a.css"] }, "brandB": {} }
+        }))
+        .unwrap();
+        assert_eq!(config.variants.len(), 2);
+        assert_eq!(
+            config.variants["brandA"].consts,
+            vec![PathBuf::from("brands/a.css")]
+        );
+        assert!(Config::from_value(json!({ "variants": { "brand.a": {} } })).is_err());
+    }
+
+    #[test]
+    fn schema_property() {
+        assert!(Config::from_value(json!({ "$schema": "./xiss.schema.json" })).is_ok());
+    }
+
+    #[test]
+    fn extends_rebases_paths() {
+        let dir = std::env::temp_dir().join(format!("xiss-config-{}", std::process::id()));
+        fs::create_dir_all(dir.join("shared")).unwrap();
+        fs::create_dir_all(dir.join("app")).unwrap();
+        fs::write(
+            dir.join("shared/xiss.json"),
+            json!({
+                "output": "build",
+                "include": [{ "path": "ui", "output": "build/ui" }, { "path": "/abs" }],
+                "map": { "path": "map.csv" },
+                "tokens": ["tokens.json"],
+                "variants": { "brand": { "consts": ["brand.css"] } },
+                "profiles": { "ci": { "output": "ci" } },
+            })
+            .to_string(),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("app/xiss.json"),
+            json!({ "extends": "../shared/xiss.json", "map": { "lock": "lock.csv" } }).to_string(),
+        )
+        .unwrap();
+
+        let base = dir.join("app/../shared");
+        let config = Config::from_file(dir.join("app/xiss.json"), None).unwrap();
+        assert_eq!(config.output, base.join("build"));
+        assert_eq!(config.include[0].path, base.join("ui"));
+        assert_eq!(config.include[0].output, Some(base.join("build/ui")));
+        assert_eq!(config.include[1].path, Path::new("/abs"));
+        assert_eq!(config.map.path, base.join("map.csv"));
+        assert_eq!(config.map.lock, Path::new("lock.csv"));
+        assert_eq!(config.tokens, [base.join("tokens.json")]);
+        assert_eq!(config.variants["brand"].consts, [base.join("brand.css")]);
+        let config = Config::from_file(dir.join("app/xiss.json"), Some("ci")).unwrap();
+        assert_eq!(config.output, base.join("ci"));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
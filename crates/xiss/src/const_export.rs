@@ -0,0 +1,186 @@
+use std::fmt::Write;
+
+use rustc_hash::FxHashMap;
+use swc_atoms::JsWord;
+use swc_css::ast::ComponentValue;
+
+use crate::{compiler::is_valid_js_ident, css::serialize_component_values};
+
+/// Const values exported to JavaScript.
+#[derive(Debug, Default)]
+pub struct ConstExports {
+    pub js: String,
+    pub ts: String,
+    pub json: String,
+}
+
+/// Exported const value.
+#[derive(Debug, Clone, PartialEq)]
+enum ConstValue {
+    Number(f64),
+    String(String),
+}
+
+impl ConstValue {
+    /// Converts a serialized CSS value, unitless numbers, `px` and `ms`
+    /// values are converted to numbers, `s` values are converted to
+    /// milliseconds.
+    fn from_css(value: &str) -> ConstValue {
+        let value = value.trim();
+        let (number, scale) = if let Some(n) = value.strip_suffix("px") {
+            (n, 1.0)
+        } else if let Some(n) = value.strip_suffix("ms") {
+            (n, 1.0)
+        } else if let Some(n) = value.strip_suffix('s') {
+            (n, 1000.0)
+        } else {
+            (value, 1.0)
+        };
+        if !number.is_empty()
+            && number
+                .chars()
+                .all(|c| c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E'))
+        {
+            if let Ok(n) = number.parse::<f64>() {
+                if n.is_finite() {
+                    return ConstValue::Number(n * scale);
+                }
+            }
+        }
+        ConstValue::String(value.to_string())
+    }
+
+    fn to_js(&self) -> String {
+        match self {
+            ConstValue::Number(n) => n.to_string(),
+            // JSON strings are valid JavaScript strings
+            ConstValue::String(s) => serde_json::to_string(s).unwrap(),
+        }
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            ConstValue::Number(n) => serde_json::Number::from_f64(*n)
+                .map(serde_json::Value::Number)
+                .unwrap_or(serde_json::Value::Null),
+            ConstValue::String(s) => serde_json::Value::String(s.clone()),
+        }
+    }
+}
+
+/// Converts a const name to a JavaScript identifier, e.g. `--MAIN-BG` to
+/// `MAIN_BG`.
+pub fn const_js_name(name: &str) -> String {
+    let name = name.trim_start_matches("--").replace('-', "_");
+    if name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", name)
+    } else {
+        name
+    }
+}
+
+/// Exports const values to JavaScript, TypeScript declarations and JSON.
+pub fn export_consts(
+    values: &FxHashMap<JsWord, Vec<ComponentValue>>,
+) -> Result<ConstExports, String> {
+    let mut values: Vec<(&str, String)> = values
+        .iter()
+        .map(|(name, value)| (&**name, serialize_component_values(value)))
+        .collect();
+    values.sort_by(|a, b| a.0.cmp(b.0));
+    export_serialized_consts(&values)
+}
+
+fn export_serialized_consts(values: &[(&str, String)]) -> Result<ConstExports, String> {
+    let mut names: FxHashMap<String, &str> = FxHashMap::default();
+    let mut exports = ConstExports::default();
+    let mut json = serde_json::Map::new();
+    for (name, value) in values {
+        let js_name = const_js_name(name);
+        if !is_valid_js_ident(&js_name) {
+            return Err(format!(
+                "Unable to export const '{}', '{}' isn't a valid JavaScript identifier",
+                name, js_name
+            ));
+        }
+        if let Some(other) = names.insert(js_name.clone(), name) {
+            return Err(format!(
+                "Unable to export consts '{}' and '{}', both are exported as '{}'",
+                other, name, js_name
+            ));
+        }
+        let value = ConstValue::from_css(value);
+        let js_value = value.to_js();
+        // writing into a string buffer doesn't fail
+        let _ = writeln!(exports.js, "export const {} = {};", js_name, js_value);
+        let _ = writeln!(
+            exports.ts,
+            "export declare const {}: {};",
+            js_name, js_value
+        );
+        json.insert(js_name, value.to_json());
+    }
+    exports.json = serde_json::to_string_pretty(&json).unwrap();
+    Ok(exports)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn export(values: &[(&str, &str)]) -> Result<ConstExports, String> {
+        let values: Vec<(&str, String)> = values
+            .iter()
+            .map(|(name, value)| (*name, value.to_string()))
+            .collect();
+        export_serialized_consts(&values)
+    }
+
+    #[test]
+    fn values() {
+        assert_eq!(ConstValue::from_css("768px"), ConstValue::Number(768.0));
+        assert_eq!(ConstValue::from_css("1.5"), ConstValue::Number(1.5));
+        assert_eq!(ConstValue::from_css("200ms"), ConstValue::Number(200.0));
+        assert_eq!(ConstValue::from_css("0.2s"), ConstValue::Number(200.0));
+        assert_eq!(
+            ConstValue::from_css("#333"),
+            ConstValue::String("#333".to_string())
+        );
+        assert_eq!(
+            ConstValue::from_css("50%"),
+            ConstValue::String("50%".to_string())
+        );
+        assert_eq!(
+            ConstValue::from_css("inset"),
+            ConstValue::String("inset".to_string())
+        );
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(const_js_name("--MAIN-BACKGROUND"), "MAIN_BACKGROUND");
+        assert_eq!(const_js_name("--2X"), "_2X");
+    }
+
+    #[test]
+    fn exports() {
+        let exports = export(&[("--TABLET", "768px"), ("--MAIN-BG", "#333")]).unwrap();
+        assert_eq!(
+            exports.js,
+            "export const TABLET = 768;\nexport const MAIN_BG = \"#333\";\n"
+        );
+        assert_eq!(
+            exports.ts,
+            "export declare const TABLET: 768;\nexport declare const MAIN_BG: \"#333\";\n"
+        );
+        assert_eq!(
+            serde_json::from_str::<serde_json::Value>(&exports.json).unwrap(),
+            serde_json::json!({ "TABLET": 768.0, "MAIN_BG": "#333" })
+        );
+    }
+
+    #[test]
+    fn name_collisions() {
+        assert!(export(&[("--A-B", "1"), ("--A_B", "2")]).is_err());
+    }
+}
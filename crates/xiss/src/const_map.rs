@@ -1,37 +1,468 @@
-use std::path::Path;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
 
-use rustc_hash::FxHashMap;
+use rustc_hash::{FxHashMap, FxHashSet};
 use swc_atoms::JsWord;
+use swc_common::errors::HANDLER;
 use swc_css::{
-    ast::{ComponentValue, Declaration, DeclarationName},
+    ast::{AtRule, AtRulePrelude, ComponentValue, Declaration, DeclarationName, Token},
     visit::{Visit, VisitWith},
 };
 
-use crate::css::process_css;
+use crate::{
+    css::{process_css, serialize_at_rule_prelude},
+    include::normalize_path,
+    mixin::{parse_mixin, Mixin},
+    tokens::extract_token_values,
+};
+
+/// Const files that are used by all modules in a directory and its
+/// subdirectories.
+pub const CONST_FILE_NAME: &str = "const.css";
+
+/// [ConstFile] contains const values declared in a const file.
+#[derive(Debug, Default)]
+pub struct ConstFile {
+    pub values: FxHashMap<JsWord, Vec<ComponentValue>>,
+    /// Custom media queries declared with `@custom-media` rules.
+    pub custom_media: FxHashMap<JsWord, String>,
+    /// Paths from `@const-import` rules, relative to the const file.
+    pub imports: Vec<JsWord>,
+    /// Mixins declared with `@mixin` rules.
+    pub mixins: FxHashMap<JsWord, Rc<Mixin>>,
+}
 
 struct ConstDecl<'a> {
-    index: &'a mut FxHashMap<JsWord, Vec<ComponentValue>>,
+    file: &'a mut ConstFile,
 }
 
 impl Visit for ConstDecl<'_> {
     fn visit_declaration(&mut self, decl: &Declaration) {
         if let DeclarationName::DashedIdent(ident) = &decl.name {
-            self.index.insert(ident.value.clone(), decl.value.clone());
+            self.file
+                .values
+                .insert(ident.value.clone(), decl.value.clone());
+        }
+    }
+
+    fn visit_at_rule(&mut self, at_rule: &AtRule) {
+        if &at_rule.name == "const-import" {
+            if let Some(path) = parse_const_import(at_rule) {
+                self.file.imports.push(path);
+            } else {
+                HANDLER.with(|handler| {
+                    handler
+                        .struct_span_err(
+                            at_rule.span,
+                            "Invalid @const-import rule, import should have a path string, e.g. \
+                             '@const-import \"../tokens/colors.css\";'",
+                        )
+                        .emit();
+                });
+            }
+        } else if &at_rule.name == "mixin" {
+            match parse_mixin(at_rule) {
+                Ok((name, mixin)) => {
+                    self.file.mixins.insert(name, Rc::new(mixin));
+                }
+                Err((span, message)) => {
+                    HANDLER.with(|handler| {
+                        handler.struct_span_err(span, &message).emit();
+                    });
+                }
+            }
+        } else if &at_rule.name == "custom-media" {
+            if let Some((name, query)) = parse_custom_media(at_rule) {
+                self.file.custom_media.insert(name, query);
+            } else {
+                HANDLER.with(|handler| {
+                    handler
+                        .struct_span_err(
+                            at_rule.span,
+                            "Invalid @custom-media rule, custom media should have a dashed name \
+                             and a media query, e.g. '@custom-media --tablet (min-width: 768px);'",
+                        )
+                        .emit();
+                });
+            }
+        } else {
+            at_rule.visit_children_with(self);
+        }
+    }
+}
+
+/// Extracts name and media query from a `@custom-media --name query;` rule.
+fn parse_custom_media(at_rule: &AtRule) -> Option<(JsWord, String)> {
+    let prelude = serialize_at_rule_prelude(at_rule.prelude.as_deref()?);
+    let (name, query) = prelude.trim().split_once(char::is_whitespace)?;
+    let query = query.trim();
+    if name.starts_with("--") && !query.is_empty() {
+        Some((name.into(), query.to_string()))
+    } else {
+        None
+    }
+}
+
+/// Extracts path string from a `@const-import "path";` rule.
+fn parse_const_import(at_rule: &AtRule) -> Option<JsWord> {
+    if let Some(AtRulePrelude::ListOfComponentValues(values)) = at_rule.prelude.as_deref() {
+        let mut result = None;
+        for v in values.children.iter() {
+            match v {
+                ComponentValue::PreservedToken(token_and_span) => match &token_and_span.token {
+                    Token::WhiteSpace { .. } => {}
+                    Token::String { value, .. } if result.is_none() => {
+                        result = Some(value.clone());
+                    }
+                    _ => return None,
+                },
+                _ => return None,
+            }
         }
+        result
+    } else {
+        None
     }
 }
 
-pub fn extract_const_values(
-    path: &Path,
-    contents: String,
-) -> Result<FxHashMap<JsWord, Vec<ComponentValue>>, String> {
-    process_css(path, contents, |handler, stylesheet| {
-        let mut values = FxHashMap::default();
-        stylesheet.visit_with(&mut ConstDecl { index: &mut values });
+pub fn extract_const_values(path: &Path, contents: String) -> Result<ConstFile, String> {
+    process_css(path, contents, |handler, _, stylesheet| {
+        let mut file = ConstFile::default();
+        stylesheet.visit_with(&mut ConstDecl { file: &mut file });
         if handler.has_errors() {
             None
         } else {
-            Some(values)
+            Some(file)
         }
     })
+    .map(|(file, _)| file)
+    .map_err(|diagnostics| diagnostics.message)
+}
+
+/// Const values resolved for modules in a directory.
+#[derive(Debug, Default, Clone)]
+pub struct ResolvedConsts {
+    pub values: FxHashMap<JsWord, Vec<ComponentValue>>,
+    pub custom_media: FxHashMap<JsWord, String>,
+    pub mixins: FxHashMap<JsWord, Rc<Mixin>>,
+    /// Const files that were used to resolve values, including missing
+    /// `const.css` files from parent directories.
+    pub files: Vec<PathBuf>,
+}
+
+/// [ConstResolver] resolves const values from `const.css` files in parent
+/// directories and from const files imported with `@const-import`.
+///
+/// Values from nested directories are shadowing values from parent
+/// directories, and values declared in a const file are shadowing imported
+/// values. Design token files are shadowed by all const files.
+#[derive(Default)]
+pub struct ConstResolver {
+    /// Design token files with values that are used by all modules.
+    tokens: Vec<PathBuf>,
+    /// Parsed const files, missing files are stored as `None`.
+    files: FxHashMap<PathBuf, Option<Rc<ConstFile>>>,
+    dirs: FxHashMap<PathBuf, Result<Rc<ResolvedConsts>, String>>,
+    /// Resolved values with variant overlays indexed by variant names and
+    /// directories.
+    variants: FxHashMap<(String, PathBuf), Result<Rc<ResolvedConsts>, String>>,
+    /// Paths to all const files that were used to resolve values, including
+    /// missing and invalid files.
+    dependencies: FxHashSet<PathBuf>,
+}
+
+impl ConstResolver {
+    /// Creates a resolver with design token files.
+    pub fn new(tokens: Vec<PathBuf>) -> Self {
+        Self {
+            tokens,
+            ..Default::default()
+        }
+    }
+
+    /// Resolves const values for modules in a directory `dir` inside of an
+    /// include root `root`.
+    pub fn resolve(&mut self, root: &Path, dir: &Path) -> Result<Rc<ResolvedConsts>, String> {
+        if let Some(resolved) = self.dirs.get(dir) {
+            return resolved.clone();
+        }
+        let result = self.resolve_dir(root, dir);
+        self.dirs.insert(dir.to_path_buf(), result.clone());
+        result
+    }
+
+    /// Resolves const values for modules in a directory `dir` with a build
+    /// variant overlay, values from overlay files are shadowing all other
+    /// values.
+    pub fn resolve_variant(
+        &mut self,
+        root: &Path,
+        dir: &Path,
+        variant: &str,
+        overlay: &[PathBuf],
+    ) -> Result<Rc<ResolvedConsts>, String> {
+        let key = (variant.to_string(), dir.to_path_buf());
+        if let Some(resolved) = self.variants.get(&key) {
+            return resolved.clone();
+        }
+        let result = self.resolve_overlay(root, dir, overlay);
+        self.variants.insert(key, result.clone());
+        result
+    }
+
+    fn resolve_overlay(
+        &mut self,
+        root: &Path,
+        dir: &Path,
+        overlay: &[PathBuf],
+    ) -> Result<Rc<ResolvedConsts>, String> {
+        let mut resolved = (*self.resolve(root, dir)?).clone();
+        for path in overlay {
+            if !path.is_file() {
+                return Err(format!("Unable to find variant const file {:?}", path));
+            }
+            self.load(path, &mut resolved, &mut Vec::new())?;
+        }
+        Ok(Rc::new(resolved))
+    }
+
+    fn resolve_dir(&mut self, root: &Path, dir: &Path) -> Result<Rc<ResolvedConsts>, String> {
+        let mut resolved = match dir.parent() {
+            Some(parent) if dir != root && dir.starts_with(root) => {
+                let parent = self.resolve(root, parent)?;
+                (*parent).clone()
+            }
+            _ => {
+                let mut resolved = ResolvedConsts::default();
+                for path in self.tokens.clone() {
+                    if !path.is_file() {
+                        return Err(format!("Unable to find design tokens file {:?}", path));
+                    }
+                    self.load(&path, &mut resolved, &mut Vec::new())?;
+                }
+                resolved
+            }
+        };
+        self.load(&dir.join(CONST_FILE_NAME), &mut resolved, &mut Vec::new())?;
+        Ok(Rc::new(resolved))
+    }
+
+    /// Loads const values from a const file and its imports.
+    fn load(
+        &mut self,
+        path: &Path,
+        resolved: &mut ResolvedConsts,
+        stack: &mut Vec<PathBuf>,
+    ) -> Result<(), String> {
+        if stack.iter().any(|p| p == path) {
+            return Err(format!("Circular const import {:?}", path));
+        }
+        self.dependencies.insert(path.to_path_buf());
+        if !resolved.files.iter().any(|p| p == path) {
+            resolved.files.push(path.to_path_buf());
+        }
+        if let Some(file) = self.file(path)? {
+            let dir = path.parent().unwrap_or(Path::new(""));
+            stack.push(path.to_path_buf());
+            for import in file.imports.iter() {
+                let import_path = normalize_path(&dir.join(&**import));
+                if !import_path.is_file() {
+                    return Err(format!(
+                        "Unable to find const file {:?} imported from {:?}",
+                        import_path, path
+                    ));
+                }
+                self.load(&import_path, resolved, stack)?;
+            }
+            stack.pop();
+            resolved.values.extend(
+                file.values
+                    .iter()
+                    .map(|(name, value)| (name.clone(), value.clone())),
+            );
+            resolved.custom_media.extend(
+                file.custom_media
+                    .iter()
+                    .map(|(name, query)| (name.clone(), query.clone())),
+            );
+            resolved.mixins.extend(
+                file.mixins
+                    .iter()
+                    .map(|(name, mixin)| (name.clone(), mixin.clone())),
+            );
+        }
+        Ok(())
+    }
+
+    /// Returns a parsed const file.
+    fn file(&mut self, path: &Path) -> Result<Option<Rc<ConstFile>>, String> {
+        if let Some(file) = self.files.get(path) {
+            return Ok(file.clone());
+        }
+        let file = if path.is_file() {
+            let contents = fs::read_to_string(path)
+                .map_err(|err| format!("Failed to read const file {:?}: {}", path, err))?;
+            let file = if path.extension().is_some_and(|ext| ext == "json") {
+                extract_token_values(&contents)
+                    .map(|values| ConstFile {
+                        values,
+                        ..Default::default()
+                    })
+                    .map_err(|err| format!("Invalid design tokens file {:?}: {}", path, err))?
+            } else {
+                extract_const_values(path, contents)
+                    .map_err(|err| format!("Invalid const file {:?}\n{}", path, err))?
+            };
+            Some(Rc::new(file))
+        } else {
+            None
+        };
+        self.files.insert(path.to_path_buf(), file.clone());
+        Ok(file)
+    }
+
+    /// Returns paths to all const files that were used to resolve values.
+    pub fn dependencies(&self) -> impl Iterator<Item = &Path> {
+        self.dependencies.iter().map(|path| path.as_path())
+    }
+
+    /// Checks if a file was used to resolve values.
+    pub fn is_dependency(&self, path: &Path) -> bool {
+        self.dependencies.contains(path)
+    }
+
+    /// Removes all resolved values, should be invoked when const files are
+    /// changed.
+    pub fn invalidate(&mut self) {
+        self.files.clear();
+        self.dirs.clear();
+        self.variants.clear();
+        self.dependencies.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    fn write(dir: &Path, path: &str, contents: &str) {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+
+    fn value(resolved: &ResolvedConsts, name: &str) -> String {
+        crate::css::serialize_component_values(&resolved.values[&JsWord::from(name)])
+    }
+
+    #[test]
+    fn resolve_hierarchy_and_imports() {
+        let dir = temp_dir("resolve");
+        write(
+            &dir,
+            "css/const.css",
+            "@custom-media --tablet (min-width: 768px);\n:const { --A: 1; --B: 1; }",
+        );
+        write(
+            &dir,
+            "css/ui/const.css",
+            "@const-import \"../../tokens/colors.css\";\n:const { --B: 2; }",
+        );
+        write(&dir, "tokens/colors.css", ":const { --B: 3; --C: 3; }");
+
+        let mut resolver = ConstResolver::default();
+        let root = dir.join("css");
+        let resolved = resolver.resolve(&root, &root.join("ui/buttons")).unwrap();
+        assert_eq!(value(&resolved, "A"), "1");
+        assert_eq!(value(&resolved, "B"), "2");
+        assert_eq!(value(&resolved, "C"), "3");
+        assert!(resolved
+            .custom_media
+            .contains_key(&JsWord::from("--tablet")));
+        assert!(resolved.files.contains(&dir.join("tokens/colors.css")));
+        assert!(resolved.files.contains(&root.join("ui/buttons/const.css")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resolve_tokens() {
+        let dir = temp_dir("tokens");
+        write(
+            &dir,
+            "tokens.json",
+            r#"{ "color": { "primary": { "$value": "red" }, "link": { "$value": "{color.primary}" } } }"#,
+        );
+        write(&dir, "css/const.css", ":const { --color-primary: blue; }");
+
+        let mut resolver = ConstResolver::new(vec![dir.join("tokens.json")]);
+        let root = dir.join("css");
+        let resolved = resolver.resolve(&root, &root).unwrap();
+        assert_eq!(value(&resolved, "color-primary"), "blue");
+        assert_eq!(value(&resolved, "color-link"), "red");
+        assert!(resolver.is_dependency(&dir.join("tokens.json")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn resolve_variant() {
+        let dir = temp_dir("variant");
+        write(&dir, "css/const.css", ":const { --A: 1; --B: 1; }");
+        write(&dir, "brands/a.css", ":const { --B: 2; }");
+
+        let mut resolver = ConstResolver::default();
+        let root = dir.join("css");
+        let overlay = vec![dir.join("brands/a.css")];
+        let resolved = resolver
+            .resolve_variant(&root, &root, "a", &overlay)
+            .unwrap();
+        assert_eq!(value(&resolved, "A"), "1");
+        assert_eq!(value(&resolved, "B"), "2");
+        assert_eq!(value(&resolver.resolve(&root, &root).unwrap(), "B"), "1");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn mixins() {
+        let file = extract_const_values(
+            Path::new("const.css"),
+            "@mixin ring($color <color>, $width <length>: 2px) { outline: $width solid $color; }"
+                .to_string(),
+        )
+        .unwrap();
+        let mixin = &file.mixins[&JsWord::from("ring")];
+        assert_eq!(mixin.params.len(), 2);
+        assert!(mixin.params[0].default.is_none());
+        assert!(mixin.params[1].default.is_some());
+
+        assert!(extract_const_values(
+            Path::new("const.css"),
+            "@mixin ring($width: 2px, $color) {}".to_string(),
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn circular_import() {
+        let dir = temp_dir("circular");
+        write(&dir, "css/const.css", "@const-import \"a.css\";");
+        write(&dir, "css/a.css", "@const-import \"const.css\";");
+
+        let mut resolver = ConstResolver::default();
+        let root = dir.join("css");
+        assert!(resolver.resolve(&root, &root).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("xiss-const-{}-{}", name, process::id()))
+    }
 }
@@ -2,35 +2,72 @@ use std::{fmt, mem::take, path::Path, sync::Arc};
 
 use parking_lot::Mutex;
 use swc_common::{
-    errors::{Handler, HANDLER},
-    FileName, SourceMap,
+    errors::{DiagnosticBuilder, Emitter, Handler, Level, HANDLER},
+    FileName, SourceFile, SourceMap, Span,
 };
 use swc_css::{
-    ast::Stylesheet,
+    ast::{AtRulePrelude, ComponentValue, Declaration, Rule, Stylesheet},
+    codegen::{writer::basic::BasicCssWriter, CodeGenerator, Emit},
     parser::{parse_file, parser::ParserConfig},
+    visit::{VisitMut, VisitMutWith},
 };
 use swc_error_reporters::{GraphicalReportHandler, PrettyEmitter, PrettyEmitterConfig};
 
-pub fn process_css<P: AsRef<Path>, R, F: FnOnce(&Handler, &mut Stylesheet) -> Option<R>>(
+/// Diagnostics reported while processing a CSS file.
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    /// Rendered diagnostics.
+    pub message: String,
+    pub errors: usize,
+    pub warnings: usize,
+}
+
+impl Diagnostics {
+    /// Appends diagnostics reported for another file or variant.
+    pub fn append(&mut self, other: Diagnostics) {
+        self.message.push_str(&other.message);
+        self.errors += other.errors;
+        self.warnings += other.warnings;
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Parses a CSS file and processes it with `func`, source file is used to
+/// resolve span positions.
+pub fn process_css<
+    P: AsRef<Path>,
+    R,
+    F: FnOnce(&Handler, &SourceFile, &mut Stylesheet) -> Option<R>,
+>(
     path: P,
     contents: String,
     func: F,
-) -> Result<R, String> {
+) -> Result<(R, Diagnostics), Diagnostics> {
     let mut errors = vec![];
     let file_name = FileName::from(path.as_ref().to_path_buf());
     let cm: Arc<SourceMap> = Default::default();
     let wr = Box::new(LockedWriter::default());
-    let emitter = PrettyEmitter::new(
-        cm.clone(),
-        wr.clone(),
-        GraphicalReportHandler::new().with_context_lines(3),
-        PrettyEmitterConfig {
-            skip_filename: false,
-        },
-    );
+    let counters = DiagnosticCounters::default();
+    let emitter = CountingEmitter {
+        emitter: Box::new(PrettyEmitter::new(
+            cm.clone(),
+            wr.clone(),
+            GraphicalReportHandler::new().with_context_lines(3),
+            PrettyEmitterConfig {
+                skip_filename: false,
+            },
+        )),
+        counters: counters.clone(),
+    };
     let handler = Handler::with_emitter(true, false, Box::new(emitter));
     let fm = cm.new_source_file(FileName::Custom(file_name.to_string()), contents);
 
+    let mut result = None;
     match parse_file::<Stylesheet>(
         &fm,
         ParserConfig {
@@ -40,9 +77,7 @@ pub fn process_css<P: AsRef<Path>, R, F: FnOnce(&Handler, &mut Stylesheet) -> Op
     ) {
         Ok(ref mut stylesheet) => {
             if errors.is_empty() {
-                if let Some(result) = HANDLER.set(&handler, || func(&handler, stylesheet)) {
-                    return Ok(result);
-                }
+                result = HANDLER.set(&handler, || func(&handler, &fm, stylesheet));
             } else {
                 for err in errors {
                     err.to_diagnostics(&handler).emit();
@@ -53,8 +88,136 @@ pub fn process_css<P: AsRef<Path>, R, F: FnOnce(&Handler, &mut Stylesheet) -> Op
             err.to_diagnostics(&handler).emit();
         }
     }
-    let error_str = take(&mut *wr.0.lock());
-    Err(error_str)
+
+    let (errors, warnings) = *counters.0.lock();
+    let diagnostics = Diagnostics {
+        message: take(&mut *wr.0.lock()),
+        errors,
+        warnings,
+    };
+    if let Some(result) = result {
+        Ok((result, diagnostics))
+    } else {
+        Err(diagnostics)
+    }
+}
+
+/// Shared error and warning counters.
+#[derive(Clone, Default)]
+struct DiagnosticCounters(Arc<Mutex<(usize, usize)>>);
+
+/// [CountingEmitter] counts errors and warnings.
+struct CountingEmitter {
+    emitter: Box<dyn Emitter>,
+    counters: DiagnosticCounters,
+}
+
+impl Emitter for CountingEmitter {
+    fn emit(&mut self, db: &DiagnosticBuilder<'_>) {
+        {
+            let mut counters = self.counters.0.lock();
+            match db.level {
+                Level::Bug | Level::Fatal | Level::PhaseFatal | Level::Error => counters.0 += 1,
+                Level::Warning => counters.1 += 1,
+                _ => {}
+            }
+        }
+        Emitter::emit(&mut *self.emitter, db);
+    }
+}
+
+/// Serializes component values into a CSS string.
+pub fn serialize_component_values(values: &[ComponentValue]) -> String {
+    let mut result = String::new();
+    {
+        let writer = BasicCssWriter::new(&mut result, None, Default::default());
+        let mut gen = CodeGenerator::new(writer, Default::default());
+        for v in values {
+            // writing into a string buffer doesn't fail
+            let _ = gen.emit(v);
+        }
+    }
+    result
+}
+
+/// Serializes a declaration value into a CSS string, unlike
+/// [serialize_component_values] values are separated with spaces.
+pub fn serialize_declaration_value(decl: &Declaration) -> String {
+    let mut result = String::new();
+    {
+        let writer = BasicCssWriter::new(&mut result, None, Default::default());
+        let mut gen = CodeGenerator::new(writer, Default::default());
+        let _ = gen.emit(&Declaration {
+            important: None,
+            ..decl.clone()
+        });
+    }
+    match result.split_once(':') {
+        Some((_, value)) => value.trim().to_string(),
+        None => String::new(),
+    }
+}
+
+/// Serializes an at-rule prelude into a CSS string.
+pub fn serialize_at_rule_prelude(prelude: &AtRulePrelude) -> String {
+    let mut result = String::new();
+    {
+        let writer = BasicCssWriter::new(&mut result, None, Default::default());
+        let mut gen = CodeGenerator::new(writer, Default::default());
+        // writing into a string buffer doesn't fail
+        let _ = gen.emit(prelude);
+    }
+    result
+}
+
+/// Parses a declaration value from a string, spans of parsed values are
+/// replaced with a `span`.
+pub fn parse_declaration_value(name: &str, value: &str, span: Span) -> Option<Vec<ComponentValue>> {
+    let cm: Arc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Anon, format!("a{{{}:{}}}", name, value));
+    let mut errors = vec![];
+    let stylesheet: Stylesheet = parse_file(&fm, Default::default(), &mut errors).ok()?;
+    if !errors.is_empty() {
+        return None;
+    }
+    if let Some(Rule::QualifiedRule(rule)) = stylesheet.rules.into_iter().next() {
+        if let Some(ComponentValue::Declaration(decl)) = rule.block.value.into_iter().next() {
+            let mut value = decl.value;
+            value.visit_mut_with(&mut ReplaceSpan(span));
+            return Some(value);
+        }
+    }
+    None
+}
+
+/// Parses a list of declarations and nested rules from a string, spans of
+/// parsed values are replaced with a `span`.
+pub fn parse_declaration_list(value: &str, span: Span) -> Option<Vec<ComponentValue>> {
+    let cm: Arc<SourceMap> = Default::default();
+    let fm = cm.new_source_file(FileName::Anon, format!("a{{{}}}", value));
+    let mut errors = vec![];
+    let stylesheet: Stylesheet = parse_file(&fm, Default::default(), &mut errors).ok()?;
+    if !errors.is_empty() {
+        return None;
+    }
+    if let Some(Rule::QualifiedRule(rule)) = stylesheet.rules.into_iter().next() {
+        let mut value = rule.block.value;
+        for v in value.iter_mut() {
+            v.visit_mut_with(&mut ReplaceSpan(span));
+        }
+        return Some(value);
+    }
+    None
+}
+
+/// [ReplaceSpan] replaces spans of nodes parsed from generated strings with
+/// a span from the module source.
+pub(crate) struct ReplaceSpan(pub Span);
+
+impl VisitMut for ReplaceSpan {
+    fn visit_mut_span(&mut self, span: &mut Span) {
+        *span = self.0;
+    }
 }
 
 #[derive(Clone, Default)]
@@ -52,6 +52,18 @@ pub struct CssMap {
     vars: IdSet,
     keyframes: IdSet,
     new_ids_buf: String,
+    transaction: Option<Transaction>,
+}
+
+/// [Transaction] stores the state of a [CssMap] before the transaction was
+/// started and IDs that were created during the transaction.
+struct Transaction {
+    modules_len: usize,
+    new_ids_buf_len: usize,
+    classes_index: usize,
+    vars_index: usize,
+    keyframes_index: usize,
+    new_ids: Vec<(u32, IdKind, SmolStr)>,
 }
 
 impl CssMap {
@@ -67,6 +79,7 @@ impl CssMap {
             vars: IdSet::new(0, build_exclude(exclude_var)?),
             keyframes: IdSet::new(0, build_exclude(exclude_keyframes)?),
             new_ids_buf: String::new(),
+            transaction: None,
         })
     }
 
@@ -94,7 +107,7 @@ impl CssMap {
                 let id = Rc::new(Id::new(
                     kind,
                     module.index,
-                    local_id.into(),
+                    SmolStr::new(local_id),
                     global_id.into(),
                 ));
                 match kind {
@@ -115,10 +128,10 @@ impl CssMap {
     /// Returns [Id] if it exists or creates a new one.
     pub fn get_id(&mut self, module_index: u32, id_kind: IdKind, local_id: &str) -> Rc<Id> {
         let module = &mut self.modules[module_index as usize];
-        let (id_char, id_set, map) = match id_kind {
-            IdKind::Class => ('C', &mut self.classes, &mut module.classes),
-            IdKind::Var => ('V', &mut self.vars, &mut module.vars),
-            IdKind::Keyframes => ('K', &mut self.keyframes, &mut module.keyframes),
+        let (id_set, map) = match id_kind {
+            IdKind::Class => (&mut self.classes, &mut module.classes),
+            IdKind::Var => (&mut self.vars, &mut module.vars),
+            IdKind::Keyframes => (&mut self.keyframes, &mut module.keyframes),
         };
         if let Some(id) = map.get(local_id) {
             id.clone()
@@ -127,33 +140,84 @@ impl CssMap {
 
             let buf = &mut self.new_ids_buf;
             buf.reserve(5 + module.id.len() + local_id.len() + global_id.len());
-            buf.push(id_char);
+            buf.push(id_kind.to_char());
             buf.push(',');
             buf.push_str(&module.id);
             buf.push(',');
-            buf.push_str(local_id);
+            xiss_map::parser::write_local_id(buf, local_id);
             buf.push(',');
             buf.push_str(&global_id);
             buf.push('\n');
 
-            let id = Rc::new(Id::new(
-                IdKind::Class,
-                module_index,
-                local_id.into(),
-                global_id,
-            ));
+            let id = Rc::new(Id::new(id_kind, module_index, local_id.into(), global_id));
             map.insert(local_id.into(), id.clone());
+            if let Some(transaction) = &mut self.transaction {
+                transaction
+                    .new_ids
+                    .push((module_index, id_kind, local_id.into()));
+            }
 
             id
         }
     }
 
+    /// Returns an existing [Id] without allocating a new one.
+    pub fn find_id(&self, module_id: &str, id_kind: IdKind, local_id: &str) -> Option<&Rc<Id>> {
+        let module = &self.modules[*self.index.get(module_id)? as usize];
+        match id_kind {
+            IdKind::Class => module.classes.get(local_id),
+            IdKind::Var => module.vars.get(local_id),
+            IdKind::Keyframes => module.keyframes.get(local_id),
+        }
+    }
+
     /// Returns module index if the module exists, otherwise creates a new one
     /// and returns its index.
     pub fn get_module_index(&mut self, module_name: &str) -> u32 {
         get_module_index(&mut self.index, &mut self.modules, module_name)
     }
 
+    /// Starts a transaction, all IDs and modules created during the
+    /// transaction are removed when it is rolled back.
+    pub fn begin(&mut self) {
+        debug_assert!(self.transaction.is_none());
+        self.transaction = Some(Transaction {
+            modules_len: self.modules.len(),
+            new_ids_buf_len: self.new_ids_buf.len(),
+            classes_index: self.classes.index(),
+            vars_index: self.vars.index(),
+            keyframes_index: self.keyframes.index(),
+            new_ids: Vec::new(),
+        });
+    }
+
+    /// Commits the current transaction.
+    pub fn commit(&mut self) {
+        self.transaction = None;
+    }
+
+    /// Rolls back the current transaction.
+    pub fn rollback(&mut self) {
+        if let Some(transaction) = self.transaction.take() {
+            for (module_index, id_kind, local_id) in transaction.new_ids {
+                if let Some(module) = self.modules.get_mut(module_index as usize) {
+                    match id_kind {
+                        IdKind::Class => module.classes.remove(&local_id),
+                        IdKind::Var => module.vars.remove(&local_id),
+                        IdKind::Keyframes => module.keyframes.remove(&local_id),
+                    };
+                }
+            }
+            for module in self.modules.drain(transaction.modules_len..) {
+                self.index.remove(&module.id);
+            }
+            self.new_ids_buf.truncate(transaction.new_ids_buf_len);
+            self.classes.set_index(transaction.classes_index);
+            self.vars.set_index(transaction.vars_index);
+            self.keyframes.set_index(transaction.keyframes_index);
+        }
+    }
+
     /// Writes new ids into the [output].
     pub fn flush_new_ids<W: Write>(&mut self, output: &mut W) -> Result<(), io::Error> {
         output.write(self.new_ids_buf.as_bytes())?;
@@ -214,3 +278,65 @@ fn insert_id(
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn css_map() -> CssMap {
+        CssMap::new(&vec![], &vec![], &vec![]).unwrap()
+    }
+
+    fn flush(css_map: &mut CssMap) -> String {
+        let mut buf = Vec::new();
+        css_map.flush_new_ids(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn commit() {
+        let mut css_map = css_map();
+        css_map.begin();
+        let m = css_map.get_module_index("m");
+        let a = css_map.get_id(m, IdKind::Class, "A");
+        css_map.commit();
+        assert!(Rc::ptr_eq(
+            css_map.find_id("m", IdKind::Class, "A").unwrap(),
+            &a
+        ));
+        assert_eq!(flush(&mut css_map), "C,m,A,a\n");
+    }
+
+    #[test]
+    fn rollback() {
+        let mut css_map = css_map();
+        let m = css_map.get_module_index("m");
+        css_map.get_id(m, IdKind::Class, "A");
+
+        css_map.begin();
+        css_map.get_id(m, IdKind::Class, "B");
+        css_map.get_id(m, IdKind::Var, "V");
+        let n = css_map.get_module_index("n");
+        css_map.get_id(n, IdKind::Keyframes, "K");
+        css_map.rollback();
+
+        assert!(css_map.find_id("m", IdKind::Class, "A").is_some());
+        assert!(css_map.find_id("m", IdKind::Class, "B").is_none());
+        assert!(css_map.find_id("m", IdKind::Var, "V").is_none());
+        assert!(css_map.find_id("n", IdKind::Keyframes, "K").is_none());
+        assert_eq!(css_map.modules.len(), 1);
+        assert_eq!(flush(&mut css_map), "C,m,A,a\n");
+    }
+
+    #[test]
+    fn rollback_reuses_global_ids() {
+        let mut css_map = css_map();
+        let m = css_map.get_module_index("m");
+        css_map.begin();
+        css_map.get_id(m, IdKind::Class, "A");
+        css_map.rollback();
+
+        let b = css_map.get_id(m, IdKind::Class, "B");
+        assert_eq!(b.global_id, "a");
+    }
+}
@@ -0,0 +1,270 @@
+use std::fmt::{self, Write};
+
+use rustc_hash::FxHashMap;
+use xiss_map::IdKind;
+
+use crate::{
+    conditional::is_keyword,
+    preprocess::{skip_comment, skip_string},
+};
+
+/// [DocComments] stores `/** ... */` comments that are declared directly
+/// above class rules, var declarations, `@keyframes` and `@classmap` rules.
+///
+/// CSS parser doesn't preserve comments, so doc comments are extracted from
+/// the module source before it is parsed. Comments are copied into JSDoc
+/// comments in generated TS files, e.g. a `@deprecated` tag is used by
+/// editors to strike out deprecated classes.
+#[derive(Debug, Default)]
+pub struct DocComments {
+    pub classes: FxHashMap<String, Vec<String>>,
+    pub vars: FxHashMap<String, Vec<String>>,
+    pub keyframes: FxHashMap<String, Vec<String>>,
+    pub class_maps: FxHashMap<String, Vec<String>>,
+}
+
+impl DocComments {
+    /// Extracts doc comments from a module source.
+    pub fn extract(source: &str) -> Self {
+        let bytes = source.as_bytes();
+        let mut result = Self::default();
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                    let end = skip_comment(source, i);
+                    if source[i..].starts_with("/**") && !source[i..].starts_with("/**/") {
+                        result.add(source, i, end);
+                    }
+                    i = end;
+                    continue;
+                }
+                b'"' | b'\'' => {
+                    i = skip_string(bytes, i);
+                    continue;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        result
+    }
+
+    /// Returns doc comment lines for an ID.
+    pub fn get(&self, kind: IdKind, local_id: &str) -> Option<&[String]> {
+        match kind {
+            IdKind::Class => self.classes.get(local_id),
+            IdKind::Var => self.vars.get(local_id),
+            IdKind::Keyframes => self.keyframes.get(local_id),
+        }
+        .map(|lines| &lines[..])
+    }
+
+    /// Adds a comment at `start..end` if it is declared above a known
+    /// declaration.
+    fn add(&mut self, source: &str, start: usize, end: usize) {
+        let body = if let Some(body) = source[start + 3..end].strip_suffix("*/") {
+            body
+        } else {
+            return;
+        };
+        let next = &source[end..];
+        let next = next.trim_start();
+        let (map, name) = if let Some(s) = next.strip_prefix('.') {
+            (&mut self.classes, parse_ident(s))
+        } else if let Some(s) = next.strip_prefix("--") {
+            let name = parse_ident(s);
+            if !s[name.1..].trim_start().starts_with(':') {
+                return;
+            }
+            (&mut self.vars, name)
+        } else if is_keyword(next, "@keyframes") {
+            (
+                &mut self.keyframes,
+                parse_ident(next["@keyframes".len()..].trim_start()),
+            )
+        } else if is_keyword(next, "@classmap") {
+            (
+                &mut self.class_maps,
+                parse_ident(next["@classmap".len()..].trim_start()),
+            )
+        } else {
+            return;
+        };
+        let (name, len) = name;
+        let lines = doc_lines(body);
+        if len == 0 || lines.is_empty() {
+            return;
+        }
+        // rules with the same ID can be declared multiple times
+        let doc = map.entry(name).or_default();
+        if !doc.is_empty() {
+            doc.push(String::new());
+        }
+        doc.extend(lines);
+    }
+}
+
+/// Parses an identifier with escaped chars, returns unescaped identifier and
+/// its length in the source.
+fn parse_ident(s: &str) -> (String, usize) {
+    let mut result = String::new();
+    let mut iter = s.char_indices().peekable();
+    while let Some(&(i, c)) = iter.peek() {
+        match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | '-' => result.push(c),
+            '\\' => {
+                iter.next();
+                match iter.next() {
+                    Some((_, c)) if c.is_ascii_hexdigit() => {
+                        let mut code = c.to_digit(16).unwrap();
+                        for _ in 0..5 {
+                            match iter.peek() {
+                                Some(&(_, c)) if c.is_ascii_hexdigit() => {
+                                    code = code * 16 + c.to_digit(16).unwrap();
+                                    iter.next();
+                                }
+                                _ => break,
+                            }
+                        }
+                        if let Some(&(_, ' ' | '\t' | '\n')) = iter.peek() {
+                            iter.next();
+                        }
+                        result.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    }
+                    Some((_, '\n')) | None => return (result, i),
+                    Some((_, c)) => result.push(c),
+                }
+                continue;
+            }
+            c if !c.is_ascii() => result.push(c),
+            _ => return (result, i),
+        }
+        iter.next();
+    }
+    (result, s.len())
+}
+
+/// Returns lines of a doc comment body without leading `*` chars.
+fn doc_lines(body: &str) -> Vec<String> {
+    let mut lines: Vec<String> = body
+        .lines()
+        .map(|line| {
+            let line = line.trim();
+            let line = line.strip_prefix('*').unwrap_or(line);
+            line.strip_prefix(' ')
+                .unwrap_or(line)
+                .trim_end()
+                .to_string()
+        })
+        .collect();
+    while lines.last().is_some_and(|line| line.is_empty()) {
+        lines.pop();
+    }
+    let empty = lines.iter().take_while(|line| line.is_empty()).count();
+    lines.drain(..empty);
+    lines
+}
+
+/// Writes a JSDoc comment with a generated summary line followed by doc
+/// comment lines, continuation lines are prefixed with `indent`.
+pub(crate) fn write_jsdoc<W: Write>(
+    output: &mut W,
+    indent: &str,
+    summary: fmt::Arguments,
+    doc: Option<&[String]>,
+) -> fmt::Result {
+    if let Some(doc) = doc {
+        write!(output, "/**\n{} * {}\n{} *\n", indent, summary, indent)?;
+        for line in doc {
+            if line.is_empty() {
+                write!(output, "{} *\n", indent)?;
+            } else {
+                write!(output, "{} * {}\n", indent, line)?;
+            }
+        }
+        write!(output, "{} */\n", indent)
+    } else {
+        write!(output, "/** {} */\n", summary)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn class() {
+        let docs = DocComments::extract(
+            "/** Primary button. */\n.button-primary { color: red; }\n.a { color: blue; }",
+        );
+        assert_eq!(
+            docs.get(IdKind::Class, "button-primary").unwrap(),
+            ["Primary button."]
+        );
+        assert!(docs.get(IdKind::Class, "a").is_none());
+    }
+
+    #[test]
+    fn multiline() {
+        let docs = DocComments::extract(
+            "/**\n * Button.\n *\n * @deprecated Use {@link Link} instead.\n */\n.Button:hover {}",
+        );
+        assert_eq!(
+            docs.get(IdKind::Class, "Button").unwrap(),
+            ["Button.", "", "@deprecated Use {@link Link} instead."]
+        );
+    }
+
+    #[test]
+    fn escaped_class() {
+        let docs = DocComments::extract("/** Doc */\n.sm\\:hidden {}\n/** Doc */\n.\\31 0 {}");
+        assert!(docs.get(IdKind::Class, "sm:hidden").is_some());
+        assert!(docs.get(IdKind::Class, "10").is_some());
+    }
+
+    #[test]
+    fn var_keyframes_class_map() {
+        let docs = DocComments::extract(
+            ".a {\n  /** Var */\n  --Size: 1px;\n}\n/** Anim */\n@keyframes fade {}\n/** Map \
+             */\n@classmap button {}\n/** Not a var */\n.b { --X: var(--Size); }",
+        );
+        assert_eq!(docs.get(IdKind::Var, "Size").unwrap(), ["Var"]);
+        assert_eq!(docs.get(IdKind::Keyframes, "fade").unwrap(), ["Anim"]);
+        assert_eq!(docs.class_maps.get("button").unwrap(), &["Map"]);
+        assert!(docs.get(IdKind::Var, "X").is_none());
+    }
+
+    #[test]
+    fn regular_comments() {
+        let docs = DocComments::extract("/* Comment */\n.a {}\n/**/\n.b {}\n/** */\n.c {}");
+        assert!(docs.classes.is_empty());
+    }
+
+    #[test]
+    fn multiple_rules() {
+        let docs = DocComments::extract("/** A */\n.a {}\n/** B */\n.a:hover {}");
+        assert_eq!(docs.get(IdKind::Class, "a").unwrap(), ["A", "", "B"]);
+    }
+
+    #[test]
+    fn jsdoc() {
+        let mut output = String::new();
+        write_jsdoc(&mut output, "  ", format_args!("class {{@link a}}"), None).unwrap();
+        assert_eq!(output, "/** class {@link a} */\n");
+
+        let mut output = String::new();
+        let doc = vec!["Doc.".to_string(), String::new(), "@deprecated".to_string()];
+        write_jsdoc(
+            &mut output,
+            "  ",
+            format_args!("class {{@link a}}"),
+            Some(&doc),
+        )
+        .unwrap();
+        assert_eq!(
+            output,
+            "/**\n   * class {@link a}\n   *\n   * Doc.\n   *\n   * @deprecated\n   */\n"
+        );
+    }
+}
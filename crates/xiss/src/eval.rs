@@ -0,0 +1,712 @@
+//! Compile-time evaluation of `calc()` and color functions.
+//!
+//! Functions are evaluated only when all arguments are literal values,
+//! expressions that can't be evaluated are left as-is. Strings and `url()`
+//! arguments are never evaluated.
+
+use crate::preprocess::skip_string;
+
+/// Functions that can be evaluated at compile time.
+const FUNCTIONS: [&str; 5] = ["calc", "color-mix", "lighten", "darken", "alpha"];
+
+/// Evaluates functions in a serialized declaration value, returns `None`
+/// when value doesn't have any functions that can be evaluated.
+pub fn eval_value(value: &str) -> Option<String> {
+    let result = fold(value);
+    if result != value {
+        Some(result)
+    } else {
+        None
+    }
+}
+
+/// Evaluates all functions in a string, nested functions are evaluated
+/// first.
+fn fold(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut i = 0;
+    while i < s.len() {
+        let end = match s.as_bytes()[i] {
+            b'"' | b'\'' => Some(skip_string(s.as_bytes(), i)),
+            _ => url_end(s, i),
+        };
+        if let Some(end) = end {
+            result.push_str(&s[i..end]);
+            i = end;
+            continue;
+        }
+        if let Some((name, args_start)) = function_at(s, i) {
+            if let Some(args_end) = find_closing_paren(s, args_start) {
+                let args = fold(&s[args_start..args_end]);
+                if let Some(value) = eval_function(name, &args) {
+                    result.push_str(&value);
+                } else {
+                    result.push_str(name);
+                    result.push('(');
+                    result.push_str(&args);
+                    result.push(')');
+                }
+                i = args_end + 1;
+                continue;
+            }
+        }
+        let c = s[i..].chars().next().unwrap();
+        result.push(c);
+        i += c.len_utf8();
+    }
+    result
+}
+
+/// Returns a function name and an index of its first argument when one of
+/// the [FUNCTIONS] starts at index `i`.
+fn function_at(s: &str, i: usize) -> Option<(&str, usize)> {
+    if s[..i].chars().next_back().is_some_and(is_ident_char) {
+        return None;
+    }
+    let rest = &s[i..];
+    for name in FUNCTIONS {
+        if rest.len() > name.len()
+            && rest[..name.len()].eq_ignore_ascii_case(name)
+            && rest[name.len()..].starts_with('(')
+        {
+            return Some((&s[i..i + name.len()], i + name.len() + 1));
+        }
+    }
+    None
+}
+
+/// Returns an index after the end of a `url()` function when it starts at
+/// index `i`.
+fn url_end(s: &str, i: usize) -> Option<usize> {
+    if s[..i].chars().next_back().is_some_and(is_ident_char) {
+        return None;
+    }
+    if !s.get(i..i + 4)?.eq_ignore_ascii_case("url(") {
+        return None;
+    }
+    Some(find_closing_paren(s, i + 4).map_or(s.len(), |end| end + 1))
+}
+
+/// Returns an index of a closing parenthesis, strings are skipped.
+fn find_closing_paren(s: &str, start: usize) -> Option<usize> {
+    let bytes = s.as_bytes();
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'"' | b'\'' => {
+                i = skip_string(bytes, i);
+                continue;
+            }
+            b'(' => depth += 1,
+            b')' => {
+                if depth == 0 {
+                    return Some(i);
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-' || c == '_'
+}
+
+fn eval_function(name: &str, args: &str) -> Option<String> {
+    match name.to_ascii_lowercase().as_str() {
+        "calc" => eval_calc(args).map(|v| v.to_string()),
+        "color-mix" => eval_color_mix(args).map(|c| c.to_string()),
+        "lighten" => eval_lightness(args, 1.0).map(|c| c.to_string()),
+        "darken" => eval_lightness(args, -1.0).map(|c| c.to_string()),
+        "alpha" => eval_alpha(args).map(|c| c.to_string()),
+        _ => None,
+    }
+}
+
+/// Numeric value with a unit, unitless numbers have an empty unit.
+#[derive(Debug, Clone, PartialEq)]
+struct Dimension {
+    value: f64,
+    unit: String,
+}
+
+impl Dimension {
+    fn parse(s: &str) -> Option<Dimension> {
+        let s = s.trim();
+        let end = s
+            .char_indices()
+            .find(|&(i, c)| {
+                !(c.is_ascii_digit()
+                    || c == '.'
+                    || (i == 0 && (c == '-' || c == '+'))
+                    || ((c == 'e' || c == 'E')
+                        && s[i + 1..].starts_with(|c: char| c.is_ascii_digit() || c == '-')))
+            })
+            .map(|(i, _)| i)
+            .unwrap_or(s.len());
+        let value: f64 = s[..end].parse().ok()?;
+        let unit = &s[end..];
+        if unit.chars().all(|c| c.is_ascii_alphabetic() || c == '%') {
+            Some(Dimension {
+                value,
+                unit: unit.to_ascii_lowercase(),
+            })
+        } else {
+            None
+        }
+    }
+
+    fn is_number(&self) -> bool {
+        self.unit.is_empty()
+    }
+}
+
+impl std::fmt::Display for Dimension {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", format_number(self.value), self.unit)
+    }
+}
+
+/// Formats a number with at most 4 fractional digits.
+fn format_number(n: f64) -> String {
+    let s = format!("{:.4}", n);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    if s == "-0" {
+        "0".to_string()
+    } else {
+        s.to_string()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum CalcToken {
+    Value(Dimension),
+    Op(char),
+    Open,
+    Close,
+}
+
+fn tokenize_calc(s: &str) -> Option<Vec<CalcToken>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.char_indices().peekable();
+    while let Some(&(i, c)) = chars.peek() {
+        let prev_is_value = matches!(
+            tokens.last(),
+            Some(CalcToken::Value(_)) | Some(CalcToken::Close)
+        );
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                tokens.push(CalcToken::Open);
+                chars.next();
+            }
+            ')' => {
+                tokens.push(CalcToken::Close);
+                chars.next();
+            }
+            '*' | '/' => {
+                tokens.push(CalcToken::Op(c));
+                chars.next();
+            }
+            '+' | '-' if prev_is_value => {
+                tokens.push(CalcToken::Op(c));
+                chars.next();
+            }
+            c if c.is_ascii_digit() || c == '.' || c == '-' || c == '+' => {
+                let mut end = s.len();
+                let mut first = true;
+                while let Some(&(j, c)) = chars.peek() {
+                    if c.is_whitespace() || c == '(' || c == ')' || c == '*' || c == '/' {
+                        end = j;
+                        break;
+                    }
+                    if !first && (c == '+' || c == '-') && !s[..j].ends_with(['e', 'E']) {
+                        end = j;
+                        break;
+                    }
+                    first = false;
+                    chars.next();
+                }
+                tokens.push(CalcToken::Value(Dimension::parse(&s[i..end])?));
+            }
+            _ => return None,
+        }
+    }
+    Some(tokens)
+}
+
+/// Evaluates `calc()` arguments when all values have compatible units.
+fn eval_calc(args: &str) -> Option<Dimension> {
+    let tokens = tokenize_calc(args)?;
+    let mut pos = 0;
+    let result = calc_sum(&tokens, &mut pos)?;
+    if pos == tokens.len() {
+        Some(result)
+    } else {
+        None
+    }
+}
+
+fn calc_sum(tokens: &[CalcToken], pos: &mut usize) -> Option<Dimension> {
+    let mut left = calc_product(tokens, pos)?;
+    while let Some(CalcToken::Op(op @ ('+' | '-'))) = tokens.get(*pos) {
+        *pos += 1;
+        let right = calc_product(tokens, pos)?;
+        if left.unit != right.unit {
+            return None;
+        }
+        if *op == '+' {
+            left.value += right.value;
+        } else {
+            left.value -= right.value;
+        }
+    }
+    Some(left)
+}
+
+fn calc_product(tokens: &[CalcToken], pos: &mut usize) -> Option<Dimension> {
+    let mut left = calc_term(tokens, pos)?;
+    while let Some(CalcToken::Op(op @ ('*' | '/'))) = tokens.get(*pos) {
+        *pos += 1;
+        let right = calc_term(tokens, pos)?;
+        if *op == '*' {
+            if right.is_number() {
+                left.value *= right.value;
+            } else if left.is_number() {
+                left = Dimension {
+                    value: left.value * right.value,
+                    unit: right.unit,
+                };
+            } else {
+                return None;
+            }
+        } else {
+            if right.value == 0.0 {
+                return None;
+            }
+            if right.is_number() {
+                left.value /= right.value;
+            } else if left.unit == right.unit {
+                left = Dimension {
+                    value: left.value / right.value,
+                    unit: String::new(),
+                };
+            } else {
+                return None;
+            }
+        }
+    }
+    Some(left)
+}
+
+fn calc_term(tokens: &[CalcToken], pos: &mut usize) -> Option<Dimension> {
+    match tokens.get(*pos)? {
+        CalcToken::Value(v) => {
+            *pos += 1;
+            Some(v.clone())
+        }
+        CalcToken::Open => {
+            *pos += 1;
+            let v = calc_sum(tokens, pos)?;
+            if tokens.get(*pos)? != &CalcToken::Close {
+                return None;
+            }
+            *pos += 1;
+            Some(v)
+        }
+        _ => None,
+    }
+}
+
+/// sRGB color with channels in a `[0, 1]` range.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Color {
+    r: f64,
+    g: f64,
+    b: f64,
+    a: f64,
+}
+
+impl Color {
+    fn parse(s: &str) -> Option<Color> {
+        let s = s.trim();
+        if let Some(hex) = s.strip_prefix('#') {
+            return Color::parse_hex(hex);
+        }
+        match s.to_ascii_lowercase().as_str() {
+            "black" => return Some(Color::rgba(0.0, 0.0, 0.0, 1.0)),
+            "white" => return Some(Color::rgba(1.0, 1.0, 1.0, 1.0)),
+            "transparent" => return Some(Color::rgba(0.0, 0.0, 0.0, 0.0)),
+            _ => {}
+        }
+        let open = s.find('(')?;
+        let name = s[..open].to_ascii_lowercase();
+        if (name == "rgb" || name == "rgba") && s.ends_with(')') {
+            let args = &s[open + 1..s.len() - 1];
+            let (channels, alpha) = if let Some((channels, alpha)) = args.split_once('/') {
+                (channels, Some(alpha))
+            } else {
+                (args, None)
+            };
+            let mut values: Vec<&str> = channels
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|s| !s.is_empty())
+                .collect();
+            let alpha = match (alpha, values.len()) {
+                (Some(alpha), 3) => parse_alpha(alpha)?,
+                (None, 4) => parse_alpha(values.pop()?)?,
+                (None, 3) => 1.0,
+                _ => return None,
+            };
+            let mut channels = [0.0; 3];
+            for (i, v) in values.iter().enumerate() {
+                let d = Dimension::parse(v)?;
+                channels[i] = match d.unit.as_str() {
+                    "" => d.value / 255.0,
+                    "%" => d.value / 100.0,
+                    _ => return None,
+                };
+            }
+            return Some(Color::rgba(channels[0], channels[1], channels[2], alpha));
+        }
+        None
+    }
+
+    fn parse_hex(hex: &str) -> Option<Color> {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let digits: Vec<u8> = match hex.len() {
+            3 | 4 => hex
+                .chars()
+                .map(|c| u8::from_str_radix(&format!("{}{}", c, c), 16).unwrap())
+                .collect(),
+            6 | 8 => (0..hex.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+                .collect(),
+            _ => return None,
+        };
+        Some(Color::rgba(
+            digits[0] as f64 / 255.0,
+            digits[1] as f64 / 255.0,
+            digits[2] as f64 / 255.0,
+            digits.get(3).map_or(1.0, |&a| a as f64 / 255.0),
+        ))
+    }
+
+    fn rgba(r: f64, g: f64, b: f64, a: f64) -> Color {
+        Color {
+            r: r.clamp(0.0, 1.0),
+            g: g.clamp(0.0, 1.0),
+            b: b.clamp(0.0, 1.0),
+            a: a.clamp(0.0, 1.0),
+        }
+    }
+
+    fn to_oklab(self) -> [f64; 3] {
+        let r = srgb_to_linear(self.r);
+        let g = srgb_to_linear(self.g);
+        let b = srgb_to_linear(self.b);
+        let l = (0.4122214708 * r + 0.5363325363 * g + 0.0514459929 * b).cbrt();
+        let m = (0.2119034982 * r + 0.6806995451 * g + 0.1073969566 * b).cbrt();
+        let s = (0.0883024619 * r + 0.2817188376 * g + 0.6299787005 * b).cbrt();
+        [
+            0.2104542553 * l + 0.7936177850 * m - 0.0040720468 * s,
+            1.9779984951 * l - 2.4285922050 * m + 0.4505937099 * s,
+            0.0259040371 * l + 0.7827717662 * m - 0.8086757660 * s,
+        ]
+    }
+
+    fn from_oklab([l, a, b]: [f64; 3], alpha: f64) -> Color {
+        let l_ = (l + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+        let m_ = (l - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+        let s_ = (l - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+        Color::rgba(
+            linear_to_srgb(4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_),
+            linear_to_srgb(-1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_),
+            linear_to_srgb(-0.0041960863 * l_ - 0.7034186147 * m_ + 1.7076147010 * s_),
+            alpha,
+        )
+    }
+
+    fn to_oklch(self) -> [f64; 3] {
+        let [l, a, b] = self.to_oklab();
+        [
+            l,
+            (a * a + b * b).sqrt(),
+            b.atan2(a).to_degrees().rem_euclid(360.0),
+        ]
+    }
+
+    fn from_oklch([l, c, h]: [f64; 3], alpha: f64) -> Color {
+        let h = h.to_radians();
+        Color::from_oklab([l, c * h.cos(), c * h.sin()], alpha)
+    }
+}
+
+impl std::fmt::Display for Color {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let to_byte = |c: f64| (c * 255.0).round() as u8;
+        write!(
+            f,
+            "#{:02x}{:02x}{:02x}",
+            to_byte(self.r),
+            to_byte(self.g),
+            to_byte(self.b)
+        )?;
+        if self.a < 1.0 {
+            write!(f, "{:02x}", to_byte(self.a))?;
+        }
+        Ok(())
+    }
+}
+
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Parses alpha value, e.g. `0.5` or `50%`.
+fn parse_alpha(s: &str) -> Option<f64> {
+    let d = Dimension::parse(s)?;
+    match d.unit.as_str() {
+        "" => Some(d.value),
+        "%" => Some(d.value / 100.0),
+        _ => None,
+    }
+}
+
+/// Splits function arguments by top-level commas.
+fn split_args(args: &str) -> Vec<&str> {
+    let mut result = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in args.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            ',' if depth == 0 => {
+                result.push(args[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    result.push(args[start..].trim());
+    result
+}
+
+/// Parses a color with an optional percentage, e.g. `#fff 20%`.
+fn parse_mix_component(s: &str) -> Option<(Color, Option<f64>)> {
+    if let Some((color, p)) = s.rsplit_once(char::is_whitespace) {
+        if let Some(d) = Dimension::parse(p) {
+            if d.unit == "%" {
+                return Some((Color::parse(color)?, Some(d.value / 100.0)));
+            }
+        }
+    }
+    Some((Color::parse(s)?, None))
+}
+
+/// Evaluates `color-mix(in <space>, <color> [<p>], <color> [<p>])`.
+fn eval_color_mix(args: &str) -> Option<Color> {
+    let args = split_args(args);
+    if args.len() != 3 {
+        return None;
+    }
+    let space = args[0].strip_prefix("in")?.trim().to_ascii_lowercase();
+    let (c1, p1) = parse_mix_component(args[1])?;
+    let (c2, p2) = parse_mix_component(args[2])?;
+    let (p1, p2) = match (p1, p2) {
+        (None, None) => (0.5, 0.5),
+        (Some(p1), None) => (p1, 1.0 - p1),
+        (None, Some(p2)) => (1.0 - p2, p2),
+        (Some(p1), Some(p2)) => (p1, p2),
+    };
+    let sum = p1 + p2;
+    if sum <= 0.0 {
+        return None;
+    }
+    let t = p2 / sum;
+    // percentages that are adding up to less than 100% are reducing alpha
+    let alpha_multiplier = sum.min(1.0);
+    let alpha = lerp(c1.a, c2.a, t);
+
+    let mut result = match space.as_str() {
+        "srgb" => {
+            // premultiplied alpha interpolation
+            let mix = |x1: f64, x2: f64| {
+                if alpha == 0.0 {
+                    0.0
+                } else {
+                    lerp(x1 * c1.a, x2 * c2.a, t) / alpha
+                }
+            };
+            Color::rgba(mix(c1.r, c2.r), mix(c1.g, c2.g), mix(c1.b, c2.b), alpha)
+        }
+        "oklab" => {
+            let lab1 = c1.to_oklab();
+            let lab2 = c2.to_oklab();
+            Color::from_oklab(
+                [
+                    lerp(lab1[0], lab2[0], t),
+                    lerp(lab1[1], lab2[1], t),
+                    lerp(lab1[2], lab2[2], t),
+                ],
+                alpha,
+            )
+        }
+        "oklch" => {
+            let lch1 = c1.to_oklch();
+            let lch2 = c2.to_oklch();
+            // shorter hue interpolation
+            let mut dh = lch2[2] - lch1[2];
+            if dh > 180.0 {
+                dh -= 360.0;
+            } else if dh < -180.0 {
+                dh += 360.0;
+            }
+            Color::from_oklch(
+                [
+                    lerp(lch1[0], lch2[0], t),
+                    lerp(lch1[1], lch2[1], t),
+                    lch1[2] + dh * t,
+                ],
+                alpha,
+            )
+        }
+        _ => return None,
+    };
+    result.a *= alpha_multiplier;
+    Some(result)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+/// Evaluates `lighten(<color>, <amount>)` and `darken(<color>, <amount>)`,
+/// lightness is adjusted in the `oklch` color space.
+fn eval_lightness(args: &str, direction: f64) -> Option<Color> {
+    let args = split_args(args);
+    if args.len() != 2 {
+        return None;
+    }
+    let color = Color::parse(args[0])?;
+    let amount = parse_alpha(args[1])?;
+    let [l, c, h] = color.to_oklch();
+    Some(Color::from_oklch(
+        [(l + amount * direction).clamp(0.0, 1.0), c, h],
+        color.a,
+    ))
+}
+
+/// Evaluates `alpha(<color>, <alpha>)`.
+fn eval_alpha(args: &str) -> Option<Color> {
+    let args = split_args(args);
+    if args.len() != 2 {
+        return None;
+    }
+    let color = Color::parse(args[0])?;
+    let alpha = parse_alpha(args[1])?;
+    Some(Color::rgba(color.r, color.g, color.b, alpha))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn calc_same_units() {
+        assert_eq!(eval_value("calc(10px + 6px)").unwrap(), "16px");
+        assert_eq!(eval_value("calc(8px * 2)").unwrap(), "16px");
+        assert_eq!(eval_value("calc(2 * (8px - 4px))").unwrap(), "8px");
+        assert_eq!(eval_value("calc(10px / 4)").unwrap(), "2.5px");
+        assert_eq!(eval_value("calc(-4px + 10px)").unwrap(), "6px");
+    }
+
+    #[test]
+    fn calc_incompatible_units() {
+        assert_eq!(eval_value("calc(100% - 16px)"), None);
+        assert_eq!(eval_value("calc(2px * 2px)"), None);
+        assert_eq!(eval_value("calc(var(--a) * 2)"), None);
+    }
+
+    #[test]
+    fn nested_functions() {
+        assert_eq!(
+            eval_value("0 calc(calc(2px * 2) + 1px) red").unwrap(),
+            "0 5px red"
+        );
+        assert_eq!(
+            eval_value("calc(100% - calc(8px * 2))").unwrap(),
+            "calc(100% - 16px)"
+        );
+    }
+
+    #[test]
+    fn color_mix() {
+        assert_eq!(
+            eval_value("color-mix(in srgb, #000, #fff)").unwrap(),
+            "#808080"
+        );
+        assert_eq!(
+            eval_value("color-mix(in srgb, #ff0000 75%, #0000ff)").unwrap(),
+            "#bf0040"
+        );
+        assert_eq!(
+            eval_value("color-mix(in oklch, #fff 100%, #000 0%)").unwrap(),
+            "#ffffff"
+        );
+        assert_eq!(eval_value("color-mix(in hsl, #000, #fff)"), None);
+        assert_eq!(eval_value("color-mix(in srgb, var(--a), #fff)"), None);
+    }
+
+    #[test]
+    fn lighten_and_darken() {
+        assert_eq!(eval_value("lighten(#000, 100%)").unwrap(), "#ffffff");
+        assert_eq!(eval_value("darken(#fff, 100%)").unwrap(), "#000000");
+        assert_eq!(eval_value("darken(#fff, 0%)").unwrap(), "#ffffff");
+    }
+
+    #[test]
+    fn alpha() {
+        assert_eq!(eval_value("alpha(#ff0000, 50%)").unwrap(), "#ff000080");
+        assert_eq!(eval_value("alpha(rgb(255 0 0), 0.5)").unwrap(), "#ff000080");
+    }
+
+    #[test]
+    fn other_functions() {
+        assert_eq!(eval_value("translate(10px, 10px)"), None);
+        assert_eq!(eval_value("mycalc(1px + 1px)"), None);
+    }
+
+    #[test]
+    fn strings_and_urls() {
+        assert_eq!(eval_value("\"calc(1 + 2)\""), None);
+        assert_eq!(eval_value("'a)' calc(1px + 2px)").unwrap(), "'a)' 3px");
+        assert_eq!(eval_value("url(calc(1+2).png)"), None);
+        assert_eq!(
+            eval_value("URL(\"a.png\") calc(1px * 2)").unwrap(),
+            "URL(\"a.png\") 2px"
+        );
+    }
+}
@@ -38,6 +38,16 @@ impl IdSet {
         self.set.insert(id.into());
     }
 
+    /// Returns an index of the next identifier candidate.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Resets an index of the next identifier candidate.
+    pub fn set_index(&mut self, index: usize) {
+        self.index = index;
+    }
+
     /// Generates a new unique identifier.
     pub fn next_id(&mut self) -> SmolStr {
         loop {
@@ -0,0 +1,333 @@
+use std::path::{Component, Path, PathBuf};
+
+use crate::config::{Config, ConfigIncludeRoot};
+
+pub const MODULE_EXTENSION: &str = "xiss";
+
+/// [IncludeRoot] is a directory with xiss modules.
+#[derive(Debug, Clone)]
+pub struct IncludeRoot {
+    /// Directory with xiss modules.
+    pub path: PathBuf,
+    /// Module ID prefix, e.g. `ui` for `ui/buttons` IDs.
+    pub prefix: String,
+    /// Output directory for compiled modules.
+    pub output: PathBuf,
+}
+
+impl IncludeRoot {
+    /// Creates an include root from a config entry, roots without an output
+    /// directory are using a shared output directory.
+    pub fn new(root: &ConfigIncludeRoot, output: &Path) -> Self {
+        let prefix = if let Some(prefix) = &root.prefix {
+            prefix.trim_matches('/').to_string()
+        } else {
+            String::new()
+        };
+        let output = if let Some(output) = &root.output {
+            strip_current_dir(output).to_path_buf()
+        } else if prefix.is_empty() {
+            output.to_path_buf()
+        } else {
+            output.join(&prefix)
+        };
+        Self {
+            path: strip_current_dir(&root.path).to_path_buf(),
+            prefix,
+            output,
+        }
+    }
+
+    /// Creates include roots from a config.
+    pub fn from_config(config: &Config) -> Vec<IncludeRoot> {
+        let output = strip_current_dir(&config.output);
+        config
+            .include
+            .iter()
+            .map(|root| IncludeRoot::new(root, output))
+            .collect()
+    }
+
+    /// Returns module ID for a module path.
+    pub fn module_id(&self, path: &Path) -> Result<String, String> {
+        let local_id = path_to_module_id(&self.path, path)?;
+        Ok(self.with_prefix(local_id))
+    }
+
+    /// Adds root prefix to a local module ID.
+    pub fn with_prefix(&self, local_id: &str) -> String {
+        if self.prefix.is_empty() {
+            local_id.to_string()
+        } else {
+            format!("{}/{}", self.prefix, local_id)
+        }
+    }
+
+    /// Returns a path without an extension for module output files.
+    pub fn output_path(&self, module_id: &str) -> PathBuf {
+        self.output.join(self.strip_prefix(module_id))
+    }
+
+    /// Removes root prefix from a module ID.
+    pub fn strip_prefix<'a>(&self, module_id: &'a str) -> &'a str {
+        if self.prefix.is_empty() {
+            module_id
+        } else if let Some(id) = module_id
+            .strip_prefix(self.prefix.as_str())
+            .and_then(|id| id.strip_prefix('/'))
+        {
+            id
+        } else {
+            module_id
+        }
+    }
+}
+
+/// Returns an include root that contains a path.
+///
+/// When include roots are nested, the deepest one is used.
+pub fn find_root(roots: &[IncludeRoot], path: &Path) -> Option<usize> {
+    roots
+        .iter()
+        .enumerate()
+        .filter(|(_, root)| path.starts_with(&root.path))
+        .max_by_key(|(_, root)| root.path.components().count())
+        .map(|(i, _)| i)
+}
+
+/// Returns true if path has a [MODULE_EXTENSION].
+pub fn has_module_extension(path: &Path) -> bool {
+    if let Some(ext) = path.extension() {
+        if ext == MODULE_EXTENSION {
+            return true;
+        }
+    }
+    false
+}
+
+/// Extracts module id from a path.
+pub fn path_to_module_id<'a>(root: &Path, path: &'a Path) -> Result<&'a str, String> {
+    let relative_path = path.strip_prefix(root).map_err(|_| {
+        format!(
+            "Invalid file path {:?}, path should be a subpath of a directory {:?}",
+            path, root
+        )
+    })?;
+    let mut module_id = if let Some(path) = relative_path.to_str() {
+        path
+    } else {
+        return Err(format!(
+            "Invalid file path {:?}, path should be a valid utf8 string",
+            relative_path,
+        ));
+    };
+
+    while let Some(i) = module_id.rfind('.') {
+        module_id = &module_id[..i];
+    }
+    Ok(module_id)
+}
+
+/// Extracts module ID from a `@module "id";` declaration at the top of a
+/// module source.
+///
+/// Only whitespace and comments are allowed before the declaration.
+pub fn module_declaration(contents: &str) -> Result<Option<&str>, String> {
+    let mut s = contents.trim_start_matches('\u{feff}');
+    loop {
+        s = s.trim_start();
+        if let Some(comment) = s.strip_prefix("/*") {
+            if let Some(end) = comment.find("*/") {
+                s = &comment[end + 2..];
+                continue;
+            }
+            return Ok(None);
+        }
+        break;
+    }
+    let s = if let Some(s) = s.strip_prefix("@module") {
+        s
+    } else {
+        return Ok(None);
+    };
+    // other at-rules, e.g. `@modules`
+    if s.starts_with(|c: char| c.is_alphanumeric() || c == '-' || c == '_') {
+        return Ok(None);
+    }
+    let s = s.trim_start();
+    let quote = match s.chars().next() {
+        Some(c @ ('"' | '\'')) => c,
+        _ => return Err("@module should have a string with a module ID".to_string()),
+    };
+    let s = &s[1..];
+    let end = if let Some(end) = s.find(quote) {
+        end
+    } else {
+        return Err("@module has an unterminated string".to_string());
+    };
+    let module_id = &s[..end];
+    if !s[end + 1..].trim_start().starts_with(';') {
+        return Err("@module declaration should end with a semicolon".to_string());
+    }
+    validate_module_id(module_id)?;
+    Ok(Some(module_id))
+}
+
+/// Checks that module ID is a valid relative path, e.g. `xiss/buttons`.
+pub fn validate_module_id(module_id: &str) -> Result<(), String> {
+    if module_id.is_empty()
+        || module_id
+            .split('/')
+            .any(|s| s.is_empty() || s == "." || s == ".." || s.contains('\\'))
+    {
+        return Err(format!(
+            "Invalid module ID \"{}\", module ID should be a relative path, e.g. \"ui/buttons\"",
+            module_id
+        ));
+    }
+    Ok(())
+}
+
+/// Normalizes a path lexically by removing `.` components and resolving `..`
+/// components.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    let mut result = PathBuf::new();
+    for c in path.components() {
+        match c {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if matches!(result.components().next_back(), Some(Component::Normal(_))) {
+                    result.pop();
+                } else {
+                    result.push(c);
+                }
+            }
+            _ => result.push(c),
+        }
+    }
+    result
+}
+
+/// Removes `./` prefix from a path.
+pub fn strip_current_dir(path: &Path) -> &Path {
+    if let Ok(path) = path.strip_prefix("./") {
+        path
+    } else {
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn root(path: &str, prefix: Option<&str>, output: Option<&str>) -> IncludeRoot {
+        IncludeRoot::new(
+            &ConfigIncludeRoot {
+                path: path.into(),
+                prefix: prefix.map(|p| p.into()),
+                output: output.map(|p| p.into()),
+            },
+            Path::new("build/css"),
+        )
+    }
+
+    #[test]
+    fn module_id_without_prefix() {
+        let r = root("css", None, None);
+        assert_eq!(
+            r.module_id(Path::new("css/buttons.xiss")).unwrap(),
+            "buttons"
+        );
+        assert_eq!(r.output_path("buttons"), Path::new("build/css/buttons"));
+    }
+
+    #[test]
+    fn module_id_with_prefix() {
+        let r = root("packages/ui/css", Some("ui"), None);
+        assert_eq!(
+            r.module_id(Path::new("packages/ui/css/buttons.xiss"))
+                .unwrap(),
+            "ui/buttons"
+        );
+        assert_eq!(
+            r.output_path("ui/buttons"),
+            Path::new("build/css/ui/buttons")
+        );
+    }
+
+    #[test]
+    fn module_id_with_prefix_and_output() {
+        let r = root(
+            "packages/admin/css",
+            Some("admin/"),
+            Some("./packages/admin/build"),
+        );
+        assert_eq!(
+            r.module_id(Path::new("packages/admin/css/a/table.xiss"))
+                .unwrap(),
+            "admin/a/table"
+        );
+        assert_eq!(
+            r.output_path("admin/a/table"),
+            Path::new("packages/admin/build/a/table")
+        );
+    }
+
+    #[test]
+    fn module_id_strips_extensions() {
+        assert_eq!(
+            path_to_module_id(Path::new("css"), Path::new("css/a/b.c.xiss")).unwrap(),
+            "a/b"
+        );
+    }
+
+    #[test]
+    fn module_id_outside_of_root() {
+        assert!(path_to_module_id(Path::new("css"), Path::new("other/b.xiss")).is_err());
+    }
+
+    #[test]
+    fn module_declarations() {
+        assert_eq!(
+            module_declaration("@module \"ui/buttons\";"),
+            Ok(Some("ui/buttons"))
+        );
+        assert_eq!(
+            module_declaration("/* buttons */\n  @module 'ui/buttons' ;\n.a {}"),
+            Ok(Some("ui/buttons"))
+        );
+        assert_eq!(module_declaration(".a {}\n@module \"a\";"), Ok(None));
+        assert_eq!(module_declaration("@modules \"a\";"), Ok(None));
+        assert!(module_declaration("@module a;").is_err());
+        assert!(module_declaration("@module \"a\" .a {}").is_err());
+        assert!(module_declaration("@module \"../a\";").is_err());
+        assert!(module_declaration("@module \"a//b\";").is_err());
+    }
+
+    #[test]
+    fn normalize() {
+        assert_eq!(
+            normalize_path(Path::new("./css/ui/../../tokens/./a.css")),
+            Path::new("tokens/a.css")
+        );
+        assert_eq!(
+            normalize_path(Path::new("../tokens/a.css")),
+            Path::new("../tokens/a.css")
+        );
+    }
+
+    #[test]
+    fn find_nested_root() {
+        let roots = vec![
+            root("css", None, None),
+            root("css/admin", Some("admin"), None),
+        ];
+        assert_eq!(
+            find_root(&roots, Path::new("css/admin/table.xiss")),
+            Some(1)
+        );
+        assert_eq!(find_root(&roots, Path::new("css/buttons.xiss")), Some(0));
+        assert_eq!(find_root(&roots, Path::new("other/buttons.xiss")), None);
+    }
+}
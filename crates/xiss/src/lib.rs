@@ -1,8 +1,23 @@
 pub mod class_map;
 pub mod compiler;
+pub mod conditional;
 pub mod config;
+pub mod const_export;
 pub mod const_map;
 pub mod css;
 pub mod css_map;
+pub mod doc_comment;
+pub mod eval;
 pub mod global_id;
 pub mod id;
+pub mod include;
+pub mod loops;
+pub mod manifest;
+pub mod mixin;
+pub mod naming;
+pub mod output;
+pub mod preprocess;
+pub mod source_map;
+pub mod theme;
+pub mod tokens;
+pub mod util;
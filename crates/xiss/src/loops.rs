@@ -0,0 +1,215 @@
+use crate::{
+    conditional::{is_ident_byte, is_keyword},
+    preprocess::{find_block_end, skip_comment, skip_string, SourceError},
+};
+
+/// [LoopExpansion] expands `@each $n in 1, 2, 3 { ... }` loops before a
+/// module is parsed, so that generated class names are renamed like
+/// hand-written class names.
+///
+/// Loop variables are interpolated with `#{$n}`. Line breaks in repeated
+/// blocks are replaced with spaces, so that line numbers in diagnostics
+/// after loops aren't changed.
+#[derive(Default)]
+pub struct LoopExpansion {
+    /// Offset of an outermost loop while loop bodies with substituted values
+    /// are expanded, errors in these bodies are reported at the loop.
+    loop_offset: Option<usize>,
+    pub errors: Vec<SourceError>,
+}
+
+impl LoopExpansion {
+    /// Expands loops, returns `None` when source doesn't have any loops.
+    pub fn expand(&mut self, source: &str) -> Option<String> {
+        let result = self.expand_source(source);
+        if let Some(index) = result.find("#{$") {
+            let name = variable_name(&result[index..]);
+            // unknown variables aren't substituted, so they are also in the
+            // source
+            let offset = source
+                .match_indices("#{$")
+                .find(|(i, _)| variable_name(&source[*i..]) == name)
+                .map_or(0, |(i, _)| i);
+            self.error(offset, format!("Cannot find a loop variable '${}'", name));
+        }
+        if result == source {
+            None
+        } else {
+            Some(result)
+        }
+    }
+
+    fn expand_source(&mut self, source: &str) -> String {
+        let bytes = source.as_bytes();
+        let mut result = String::with_capacity(source.len());
+        let mut last = 0;
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                    i = skip_comment(source, i);
+                }
+                b'"' | b'\'' => {
+                    i = skip_string(bytes, i);
+                }
+                b'@' if is_keyword(&source[i..], "@each") => {
+                    if let Some((end, expanded)) = self.expand_each(source, i) {
+                        result.push_str(&source[last..i]);
+                        result.push_str(&expanded);
+                        last = end;
+                        i = end;
+                    } else {
+                        i += 1;
+                    }
+                }
+                _ => i += 1,
+            }
+        }
+        result.push_str(&source[last..]);
+        result
+    }
+
+    /// Expands a loop that starts at `start`, returns an index after the end
+    /// of the loop and an expanded source.
+    fn expand_each(&mut self, source: &str, start: usize) -> Option<(usize, String)> {
+        let prelude_start = start + "@each".len();
+        let block_start = match source[prelude_start..].find(['{', ';', '}']) {
+            Some(index) if source.as_bytes()[prelude_start + index] == b'{' => {
+                prelude_start + index
+            }
+            _ => {
+                self.error(start, "@each should have a block".to_string());
+                return None;
+            }
+        };
+        let (name, values) = match parse_each_prelude(&source[prelude_start..block_start]) {
+            Some(prelude) => prelude,
+            None => {
+                self.error(
+                    start,
+                    format!(
+                        "Invalid @each rule '{}', expected '@each $name in value1, value2'",
+                        source[start..block_start].trim()
+                    ),
+                );
+                return None;
+            }
+        };
+        let block_end = match find_block_end(source, block_start) {
+            Some(end) => end,
+            None => {
+                self.error(start, "@each block isn't closed".to_string());
+                return None;
+            }
+        };
+
+        let body = &source[block_start + 1..block_end];
+        let pattern = format!("#{{${}}}", name);
+        let mut result = "\n".repeat(source[start..block_start + 1].matches('\n').count());
+        let loop_offset = self.loop_offset;
+        self.loop_offset = loop_offset.or(Some(start));
+        for (index, value) in values.iter().enumerate() {
+            let expanded = self.expand_source(&body.replace(&pattern, value));
+            if index == 0 {
+                result.push_str(&expanded);
+            } else {
+                result.push_str(&expanded.replace('\n', " "));
+            }
+        }
+        self.loop_offset = loop_offset;
+        Some((block_end + 1, result))
+    }
+
+    fn error(&mut self, offset: usize, message: String) {
+        self.errors.push(SourceError::new(
+            self.loop_offset.unwrap_or(offset),
+            message,
+        ));
+    }
+}
+
+/// Returns a name of a variable interpolation at the start of `s`.
+fn variable_name(s: &str) -> &str {
+    let name = &s["#{$".len()..];
+    let end = name
+        .bytes()
+        .position(|c| !is_ident_byte(c))
+        .unwrap_or(name.len());
+    &name[..end]
+}
+
+/// Parses `$name in value1, value2` prelude.
+fn parse_each_prelude(prelude: &str) -> Option<(&str, Vec<&str>)> {
+    let prelude = prelude.trim().strip_prefix('$')?;
+    let name_end = prelude
+        .bytes()
+        .position(|c| !is_ident_byte(c))
+        .unwrap_or(prelude.len());
+    let name = &prelude[..name_end];
+    let rest = prelude[name_end..].trim_start();
+    if name.is_empty() || !is_keyword(rest, "in") {
+        return None;
+    }
+    let values: Vec<&str> = rest["in".len()..].split(',').map(str::trim).collect();
+    if values.iter().any(|value| value.is_empty()) {
+        return None;
+    }
+    Some((name, values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(source: &str) -> (Option<String>, Vec<SourceError>) {
+        let mut loops = LoopExpansion::default();
+        let result = loops.expand(source);
+        (result, loops.errors)
+    }
+
+    #[test]
+    fn each() {
+        assert_eq!(
+            expand("@each $n in 1, 2 { .Gap#{$n} { gap: calc(#{$n} * 4px); } }")
+                .0
+                .unwrap(),
+            " .Gap1 { gap: calc(1 * 4px); }  .Gap2 { gap: calc(2 * 4px); } "
+        );
+    }
+
+    #[test]
+    fn nested() {
+        assert_eq!(
+            expand("@each $a in x, y {@each $b in 1, 2 {.#{$a}#{$b}{}}}")
+                .0
+                .unwrap(),
+            ".x1{}.x2{}.y1{}.y2{}"
+        );
+    }
+
+    #[test]
+    fn preserves_line_numbers() {
+        let source = "@each $n in 1, 2, 3 {\n.A#{$n} {}\n}\n.B {}";
+        let result = expand(source).0.unwrap();
+        assert_eq!(result.matches('\n').count(), source.matches('\n').count());
+        assert!(result.ends_with("\n.B {}"));
+    }
+
+    #[test]
+    fn invalid_loops() {
+        assert_eq!(expand("@each n in 1, 2 {}").1.len(), 1);
+        assert_eq!(expand("@each $n in 1, , 2 {}").1.len(), 1);
+        assert_eq!(expand("@each $n in 1 { .a {}").1.len(), 1);
+        assert_eq!(expand("@each $n in 1 { .A#{$m} {} }").1.len(), 1);
+    }
+
+    #[test]
+    fn error_offsets() {
+        let offsets =
+            |source| -> Vec<usize> { expand(source).1.iter().map(|err| err.offset).collect() };
+        assert_eq!(offsets(".a {}\n@each n in 1, 2 {}"), [6]);
+        assert_eq!(offsets(".a {}\n@each $n in 1 {\n  @each m in 2 {}\n}"), [6]);
+        assert_eq!(offsets(".a {}\n.B#{$n} {}"), [8]);
+        assert_eq!(offsets("@each $nn in 1 { .A#{$nn} {} .B#{$n} {} }"), [31]);
+    }
+}
@@ -1,59 +1,104 @@
 use std::{
     env, fs, io,
     path::{Path, PathBuf},
-    time::{Duration, SystemTime},
+    process::ExitCode,
+    time::Duration,
 };
 
 use clap::Parser;
 use color_eyre::eyre::{self, WrapErr};
 use ctrlc;
+use fs2::FileExt;
 use rustc_hash::{FxHashMap, FxHashSet};
-use swc_atoms::JsWord;
-use swc_css::ast::ComponentValue;
-use tracing::{error, info, trace, Level};
+use tracing::{error, info, trace, warn, Level};
 use tracing_subscriber::FmtSubscriber;
 use walkdir::WalkDir;
 use xiss::{
-    class_map::ClassMapOutput, compiler::compile, config::Config, const_map::extract_const_values,
+    class_map::ClassMapOutput,
+    compiler::{compile, CompilerOptions, IdOutput},
+    config::Config,
+    const_export::export_consts,
+    const_map::{ConstResolver, ResolvedConsts, CONST_FILE_NAME},
+    css::Diagnostics,
     css_map::CssMap,
+    include::{find_root, has_module_extension, module_declaration, IncludeRoot},
+    manifest::{Manifest, ModuleInputs},
+    naming::TsNaming,
+    output::{relative_url, write_atomic},
+    source_map::source_map_json,
+    util::hash,
 };
 
-const MODULE_EXTENSION: &str = "xiss";
-
 #[derive(Debug, Parser)]
 #[command(name = "xiss")]
 #[command(author = "Boris Kaul <localvoid@gmail.com")]
 #[command(version = "0.1")]
 #[command(about = "Compiler for .xiss CSS modules", long_about=None)]
 struct Cli {
-    /// Path to a config file
-    #[arg(short, long, default_value = "xiss.json")]
-    config: PathBuf,
+    /// Path to a config file [default: xiss.json or xiss.toml]
+    #[arg(short, long)]
+    config: Option<PathBuf>,
     #[arg(short, long)]
     /// Watch mode
     watch: bool,
     #[arg(short, long)]
-    /// Purge output files
+    /// Purge stale output files
     purge: bool,
+    /// List stale output files without removing them
+    #[arg(long)]
+    dry_run: bool,
     /// Reset CSS map
     #[arg(short, long)]
     reset: bool,
     /// Force update
     #[arg(short, long)]
     force: bool,
-    /// Class map output type
-    #[arg(long, default_value_t = ClassMapOutput::Inline)]
-    class_map: ClassMapOutput,
+    /// Class map output type [default: inline]
+    #[arg(long)]
+    class_map: Option<ClassMapOutput>,
+    /// Naming transform for TS enum members [default: camelCase]
+    #[arg(long)]
+    ts_naming: Option<TsNaming>,
+    /// ID output type [default: constEnum]
+    #[arg(long)]
+    id_output: Option<IdOutput>,
+    /// Minify CSS output
+    #[arg(long)]
+    minify: bool,
+    /// Emit `.d.ts.map` declaration maps
+    #[arg(long)]
+    declaration_map: bool,
+    /// Treat warnings as errors
+    #[arg(long)]
+    deny_warnings: bool,
+    /// Config profile
+    #[arg(long)]
+    profile: Option<String>,
+    /// Define a value for `flag()` and `define()`, flags without a value are
+    /// enabled
+    #[arg(short = 'D', long = "define", value_name = "NAME[=VALUE]")]
+    defines: Vec<String>,
     /// Verbose output
     #[arg(short, long)]
     verbose: bool,
+    /// Print JSON Schema for config files
+    #[arg(long)]
+    print_schema: bool,
 }
 
-fn main() -> eyre::Result<()> {
+/// Config files that are used when config path isn't specified.
+const DEFAULT_CONFIG_FILES: [&str; 2] = ["xiss.json", "xiss.toml"];
+
+fn main() -> eyre::Result<ExitCode> {
     color_eyre::install()?;
 
     let args = Cli::parse();
 
+    if args.print_schema {
+        println!("{}", Config::json_schema());
+        return Ok(ExitCode::SUCCESS);
+    }
+
     let subscriber = FmtSubscriber::builder()
         .with_max_level(if args.verbose {
             Level::TRACE
@@ -67,40 +112,74 @@ fn main() -> eyre::Result<()> {
     let cwd = env::current_dir()?;
     trace!("CWD {:?}", cwd);
 
-    trace!("Config path {:?}", args.config);
-    let config = if args.config.is_file() {
-        Config::from_file(&args.config)?
+    let config_path = args.config.clone().or_else(|| {
+        DEFAULT_CONFIG_FILES
+            .iter()
+            .map(PathBuf::from)
+            .find(|path| path.is_file())
+    });
+    trace!("Config path {:?}", config_path);
+    let config = if let Some(config_path) = &config_path {
+        Config::from_file(config_path, args.profile.as_deref())?
+    } else if let Some(profile) = &args.profile {
+        return Err(eyre::eyre!(
+            "Unable to use profile \"{}\" without a config file",
+            profile
+        ));
     } else {
         Config::default()
     };
+    trace!("Profile {:?}", args.profile);
 
-    trace!("Output directory {:?}", config.output);
-    let output = if let Ok(output) = config.output.strip_prefix("./") {
-        output
-    } else {
-        &config.output
-    };
-    if !output.exists() {
-        fs::create_dir_all(output)
-            .wrap_err_with(|| format!("Failed to create output directory {:?}", output))?;
-    } else if !config.output.is_dir() {
-        return Err(eyre::eyre!(
-            "Output directory {:?} is not a directory",
-            output
-        ));
+    let mut compiler_options = config.compiler_options();
+    if let Some(class_map) = args.class_map {
+        compiler_options.class_map = class_map;
+    }
+    if let Some(ts_naming) = args.ts_naming {
+        compiler_options.ts_naming = ts_naming;
+    }
+    if let Some(id_output) = args.id_output {
+        compiler_options.id_output = id_output;
+    }
+    if args.minify {
+        compiler_options.minify = true;
+    }
+    if args.declaration_map {
+        compiler_options.declaration_map = true;
+    }
+    for define in args.defines.iter() {
+        let (name, value) = define.split_once('=').unwrap_or((define, "true"));
+        compiler_options
+            .defines
+            .insert(name.to_string(), value.to_string());
     }
 
-    trace!("Include directory {:?}", config.include);
-    let include = if let Ok(include) = config.include.strip_prefix("./") {
-        include
-    } else {
-        &config.include
-    };
-    if !include.is_dir() {
-        return Err(eyre::eyre!(
-            "Invalid include path {:?}, include path should be a directory",
-            include
-        ));
+    let roots = IncludeRoot::from_config(&config);
+    if roots.is_empty() {
+        return Err(eyre::eyre!("Config should have at least one include path"));
+    }
+    for root in roots.iter() {
+        trace!(
+            "Include directory {:?} [prefix: {:?}, output: {:?}]",
+            root.path,
+            root.prefix,
+            root.output
+        );
+        if !root.path.is_dir() {
+            return Err(eyre::eyre!(
+                "Invalid include path {:?}, include path should be a directory",
+                root.path
+            ));
+        }
+        if !root.output.exists() {
+            fs::create_dir_all(&root.output)
+                .wrap_err_with(|| format!("Failed to create output directory {:?}", root.output))?;
+        } else if !root.output.is_dir() {
+            return Err(eyre::eyre!(
+                "Output directory {:?} is not a directory",
+                root.output
+            ));
+        }
     }
 
     let mut css_map = CssMap::new(
@@ -118,347 +197,773 @@ fn main() -> eyre::Result<()> {
             format!("Failed to import css map lock file {:?}", config.map.lock)
         })?;
     }
-    let css_map_file = if config.map.path.is_file() && !args.reset {
-        let file = fs::OpenOptions::new()
-            .read(true)
-            .append(true)
-            .open(&config.map.path)
-            .wrap_err_with(|| format!("Failed to open css map file {:?}", config.map.path))?;
-        let mut reader = io::BufReader::new(file);
+    if let Some(dir) = config.map.path.parent() {
+        fs::create_dir_all(dir).wrap_err_with(|| {
+            format!(
+                "Failed to create a directory for a css map file {:?}",
+                config.map
+            )
+        })?;
+    }
+    // css map file shouldn't be truncated before the lock is acquired
+    let css_map_file = fs::OpenOptions::new()
+        .create(true)
+        .read(true)
+        .append(true)
+        .open(&config.map.path)
+        .wrap_err_with(|| format!("Failed to open css map file {:?}", config.map.path))?;
+    if let Err(err) = css_map_file.try_lock_exclusive() {
+        if err.kind() == fs2::lock_contended_error().kind() {
+            return Err(eyre::eyre!(
+                "CSS map file {:?} is locked by another xiss process",
+                config.map.path
+            ));
+        }
+        return Err(err)
+            .wrap_err_with(|| format!("Failed to lock css map file {:?}", config.map.path));
+    }
+    let css_map_file = if args.reset {
+        css_map_file
+            .set_len(0)
+            .wrap_err_with(|| format!("Failed to reset css map file {:?}", config.map.path))?;
+        css_map_file
+    } else {
+        let mut reader = io::BufReader::new(css_map_file);
         css_map
             .import(&mut reader)
             .wrap_err_with(|| format!("Failed to import css map file {:?}", config.map.path))?;
         reader.into_inner()
-    } else {
-        if let Some(dir) = config.map.path.parent() {
-            fs::create_dir_all(dir).wrap_err_with(|| {
-                format!(
-                    "Failed to create a directory for a css map file {:?}",
-                    config.map
-                )
-            })?;
-        }
-        fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            .truncate(true)
-            .open(&config.map.path)
-            .wrap_err_with(|| format!("Failed to open css map file {:?}", config.map.path))?
     };
-    let mut css_map_writer = io::BufWriter::new(css_map_file);
-
-    let const_map_path = include.join("const.css");
-    let const_map = if const_map_path.is_file() {
-        let const_map_content = fs::read_to_string(&const_map_path)
-            .wrap_err_with(|| format!("Failed to read const map file {:?}", const_map_path))?;
-        match extract_const_values(&const_map_path, const_map_content) {
-            Ok(result) => result,
+    let css_map_writer = io::BufWriter::new(css_map_file);
+
+    let mut manifest = if config.manifest.is_file() {
+        match Manifest::from_file(&config.manifest) {
+            Ok(manifest) => Some(manifest),
             Err(err) => {
-                return Err(eyre::eyre!(
-                    "Invalid const map file {:?}\n{}",
-                    const_map_path,
-                    err
-                ));
+                warn!(
+                    "Invalid build manifest {:?}, all modules will be recompiled: {}",
+                    config.manifest, err
+                );
+                None
             }
         }
     } else {
-        FxHashMap::default()
+        None
     };
+    // output files that weren't recorded in a manifest can't be distinguished
+    // from files that weren't produced by xiss
+    if (args.purge || args.dry_run) && manifest.is_none() {
+        return Err(eyre::eyre!(
+            "Unable to purge output files without a valid build manifest {:?}, run a build \
+             without --purge first",
+            config.manifest
+        ));
+    }
+    if args.reset {
+        if let Some(manifest) = &mut manifest {
+            manifest.modules.clear();
+        }
+    }
 
-    let mut modules = FxHashSet::default();
-
-    build(
-        &mut modules,
-        &mut css_map,
-        &mut css_map_writer,
-        &const_map,
-        output,
-        include,
-        args.force || args.reset,
-        args.class_map,
-    )?;
+    let mut builder = Builder {
+        roots,
+        consts: ConstResolver::new(config.tokens.clone()),
+        css_map,
+        css_map_writer,
+        manifest: manifest.unwrap_or_default(),
+        manifest_path: config.manifest.clone(),
+        modules: FxHashMap::default(),
+        module_ids: FxHashMap::default(),
+        compiler_options,
+        const_files: FxHashMap::default(),
+        const_json: config.const_json,
+        variants: config
+            .variants
+            .iter()
+            .map(|(name, variant)| (name.clone(), variant.consts.clone()))
+            .collect(),
+        deny_warnings: args.deny_warnings,
+        stats: BuildStats::default(),
+    };
+
+    builder.build(args.force || args.reset)?;
+    builder.stats.report();
+    let failed = builder.stats.is_failed(args.deny_warnings);
 
-    if args.purge {
-        purge_output_files(&mut modules, output)?;
+    if args.purge || args.dry_run {
+        builder.purge_output_files(args.dry_run)?;
     }
 
     if args.watch {
-        watch(
-            &mut modules,
-            &mut css_map,
-            &mut css_map_writer,
-            &const_map,
-            &cwd,
-            output,
-            include,
-            args.class_map,
-        )?;
+        builder.watch(&cwd)?;
+    } else if failed {
+        return Ok(ExitCode::FAILURE);
     }
 
-    Ok(())
+    Ok(ExitCode::SUCCESS)
 }
 
-fn update_module<W: io::Write>(
-    css_map: &mut CssMap,
-    css_map_writer: &mut W,
-    const_map: &FxHashMap<JsWord, Vec<ComponentValue>>,
-    output: &Path,
-    path: &Path,
-    module_id: &str,
-    force_update: bool,
-    class_map_output: ClassMapOutput,
-) -> eyre::Result<()> {
-    let out_module_path = output.join(module_id);
-    let css_path = out_module_path.with_extension("css");
-    let js_path = css_path.with_extension("js");
-    let ts_path = css_path.with_extension("d.ts");
-
-    if force_update || should_compile(path, &css_path, &js_path, &ts_path) {
-        match fs::read_to_string(path) {
-            Ok(contents) => {
-                trace!("Compiling module \"{}\"", module_id);
-                match compile(
-                    path,
-                    contents,
-                    css_map,
-                    const_map,
-                    module_id,
-                    class_map_output,
-                ) {
-                    Ok(artifact) => {
-                        css_map
-                            .flush_new_ids(css_map_writer)
-                            .wrap_err("Failed to update css map")?;
-                        if let Some(dirname) = css_path.parent() {
-                            if !dirname.exists() {
-                                if let Err(err) = fs::create_dir_all(dirname) {
-                                    error!(
-                                        "Unable to create output directory {:?}: {}",
-                                        dirname, err
-                                    );
-                                }
-                            }
+/// [Builder] contains a state that is shared between module compilations.
+struct Builder<W: io::Write> {
+    roots: Vec<IncludeRoot>,
+    consts: ConstResolver,
+    css_map: CssMap,
+    css_map_writer: W,
+    manifest: Manifest,
+    manifest_path: PathBuf,
+    /// Known modules indexed by module IDs.
+    modules: FxHashMap<String, Module>,
+    /// Module IDs indexed by module paths.
+    module_ids: FxHashMap<PathBuf, String>,
+    compiler_options: CompilerOptions,
+    /// `const.css` files inside of include roots that are exported to
+    /// JavaScript, indexed by paths.
+    const_files: FxHashMap<PathBuf, usize>,
+    /// Exports const values to JSON.
+    const_json: bool,
+    /// Build variants with paths to const overlay files.
+    variants: Vec<(String, Vec<PathBuf>)>,
+    /// Modules with warnings are treated as failed.
+    deny_warnings: bool,
+    stats: BuildStats,
+}
+
+/// [Module] is a known xiss module.
+struct Module {
+    root_index: usize,
+    path: PathBuf,
+    /// Const files that were used to compile the module, `None` when const
+    /// values can't be resolved.
+    const_files: Option<Vec<PathBuf>>,
+}
+
+impl<W: io::Write> Builder<W> {
+    /// Registers a module and compiles it.
+    ///
+    /// Module ID is declared with a `@module` rule or derived from a module
+    /// path.
+    fn add_module(
+        &mut self,
+        root_index: usize,
+        path: &Path,
+        force_update: bool,
+    ) -> eyre::Result<()> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(err) => {
+                self.stats.failed += 1;
+                self.stats.errors += 1;
+                error!("Unable to read xiss file {:?}: {}", path, err);
+                return Ok(());
+            }
+        };
+        let module_id = match module_declaration(&contents) {
+            Ok(Some(module_id)) => Ok(module_id.to_string()),
+            Ok(None) => self.roots[root_index].module_id(path),
+            Err(err) => Err(format!("Invalid module declaration in {:?}: {}", path, err)),
+        };
+        let module_id = match module_id {
+            Ok(module_id) => module_id,
+            Err(err) => {
+                self.stats.failed += 1;
+                self.stats.errors += 1;
+                error!("{}", err);
+                return Ok(());
+            }
+        };
+
+        // module declaration has been changed
+        if let Some(prev_id) = self.module_ids.get(path) {
+            if *prev_id != module_id {
+                let prev_id = prev_id.clone();
+                self.remove_module(&prev_id);
+            }
+        }
+        if let Some(module) = self.modules.get(&module_id) {
+            if module.path != path {
+                self.stats.failed += 1;
+                self.stats.errors += 1;
+                error!(
+                    "Duplicate module ID \"{}\" in {:?}, module ID is already used by {:?}",
+                    module_id, path, module.path
+                );
+                return Ok(());
+            }
+        } else {
+            trace!("Module \"{}\" added: {:?}", module_id, path);
+            self.modules.insert(
+                module_id.clone(),
+                Module {
+                    root_index,
+                    path: path.to_path_buf(),
+                    const_files: None,
+                },
+            );
+            self.module_ids
+                .insert(path.to_path_buf(), module_id.clone());
+        }
+
+        self.update_module(root_index, path, &module_id, contents, force_update)
+    }
+
+    /// Unregisters a module and removes its output files.
+    fn remove_module(&mut self, module_id: &str) {
+        if let Some(module) = self.modules.remove(module_id) {
+            trace!("Module \"{}\" removed: {:?}", module_id, module.path);
+            self.module_ids.remove(&module.path);
+            self.manifest.remove(module_id);
+            for path in self.all_output_paths(module.root_index, module_id) {
+                if try_remove_file(&path) {
+                    self.manifest.remove_artifact(module_id, &path);
+                }
+            }
+        }
+    }
+
+    fn update_module(
+        &mut self,
+        root_index: usize,
+        path: &Path,
+        module_id: &str,
+        contents: String,
+        force_update: bool,
+    ) -> eyre::Result<()> {
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let root_path = &self.roots[root_index].path;
+        let consts = self.consts.resolve(root_path, dir).and_then(|consts| {
+            let variants = self
+                .variants
+                .iter()
+                .map(|(name, overlay)| {
+                    self.consts
+                        .resolve_variant(root_path, dir, name, overlay)
+                        .map(|consts| (name.clone(), consts))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok((consts, variants))
+        });
+        if let Some(module) = self.modules.get_mut(module_id) {
+            module.const_files = consts.as_ref().ok().map(|(consts, variants)| {
+                consts
+                    .files
+                    .iter()
+                    .chain(variants.iter().flat_map(|(_, consts)| consts.files.iter()))
+                    .cloned()
+                    .collect()
+            });
+        }
+        let (consts, variant_consts) = match consts {
+            Ok(consts) => consts,
+            Err(err) => {
+                self.manifest.remove(module_id);
+                self.stats.failed += 1;
+                self.stats.errors += 1;
+                error!("Failed to compile {:?}\n{}", path, err);
+                return Ok(());
+            }
+        };
+        let variants: Vec<(&str, &ResolvedConsts)> = variant_consts
+            .iter()
+            .map(|(name, consts)| (name.as_str(), &**consts))
+            .collect();
+        let [css_path, js_path, ts_path] = self.output_paths(root_index, module_id);
+
+        let inputs = ModuleInputs {
+            module_id,
+            source_hash: hash(&contents),
+            options_hash: hash(&self.compiler_options),
+            consts: &consts,
+            variants: &variants,
+        };
+        if !force_update
+            && self.manifest.is_fresh(&inputs, &self.css_map)
+            && self
+                .all_output_paths(root_index, module_id)
+                .iter()
+                .all(|path| path.is_file())
+        {
+            for path in self.all_output_paths(root_index, module_id) {
+                self.manifest.add_artifact(module_id, &path);
+            }
+            return Ok(());
+        }
+
+        trace!("Compiling module \"{}\"", module_id);
+        // IDs allocated by failed compilations shouldn't leak into the css map
+        self.css_map.begin();
+        // variants are using the same IDs, only CSS outputs are different
+        let mut variant_css = Vec::with_capacity(variants.len());
+        // warnings from variants are reported with module warnings
+        let mut variant_diagnostics = Diagnostics::default();
+        let mut result = Ok(());
+        for (name, consts) in variants.iter() {
+            match compile(
+                path,
+                contents.clone(),
+                &mut self.css_map,
+                consts,
+                module_id,
+                &self.compiler_options,
+            ) {
+                Ok(artifact) => {
+                    if artifact.diagnostics.warnings > 0 {
+                        variant_diagnostics.append(Diagnostics {
+                            message: format!(
+                                "Variant \"{}\"\n{}",
+                                name, artifact.diagnostics.message
+                            ),
+                            ..artifact.diagnostics
+                        });
+                    }
+                    variant_css.push((*name, artifact.css));
+                }
+                Err(mut diagnostics) => {
+                    diagnostics.message = format!("Variant \"{}\"\n{}", name, diagnostics.message);
+                    result = Err(diagnostics);
+                    break;
+                }
+            }
+        }
+        let result = result.and_then(|_| {
+            compile(
+                path,
+                contents,
+                &mut self.css_map,
+                &consts,
+                module_id,
+                &self.compiler_options,
+            )
+        });
+        match result.map(|mut artifact| {
+            artifact.diagnostics.append(variant_diagnostics);
+            artifact
+        }) {
+            Ok(artifact) if self.deny_warnings && artifact.diagnostics.warnings > 0 => {
+                self.css_map.rollback();
+                self.manifest.remove(module_id);
+                self.stats.failed += 1;
+                self.stats.warnings += artifact.diagnostics.warnings;
+                error!("Failed to compile {:?}\n{}", path, artifact.diagnostics);
+            }
+            Ok(artifact) => {
+                if artifact.diagnostics.warnings > 0 {
+                    self.stats.warnings += artifact.diagnostics.warnings;
+                    warn!(
+                        "Compiled {:?} with warnings\n{}",
+                        path, artifact.diagnostics
+                    );
+                }
+                self.css_map.commit();
+                self.css_map
+                    .flush_new_ids(&mut self.css_map_writer)
+                    .wrap_err("Failed to update css map")?;
+                self.manifest.update(&inputs, &self.css_map, &artifact);
+                if let Some(dirname) = css_path.parent() {
+                    if !dirname.exists() {
+                        if let Err(err) = fs::create_dir_all(dirname) {
+                            error!("Unable to create output directory {:?}: {}", dirname, err);
                         }
-                        try_update_output_file(&css_path, &artifact.css);
-                        try_update_output_file(&js_path, &artifact.js);
-                        try_update_output_file(&ts_path, &artifact.ts);
                     }
-                    Err(err) => {
-                        error!("Failed to compile {:?}\n{}", path, err);
+                }
+                let mut ts = artifact.ts;
+                let mut declaration_map = None;
+                if self.compiler_options.declaration_map {
+                    let map_path = self.declaration_map_path(root_index, module_id);
+                    let source = relative_url(map_path.parent().unwrap_or(Path::new("")), path);
+                    let map = source_map_json(&file_name(&ts_path), &source, &artifact.ts_mappings);
+                    ts.push_str(&format!("//# sourceMappingURL={}\n", file_name(&map_path)));
+                    declaration_map = Some((map_path, map));
+                }
+                let mut outputs = vec![
+                    (css_path, artifact.css),
+                    (js_path, artifact.js),
+                    (ts_path, ts),
+                ];
+                outputs.extend(declaration_map);
+                for (name, css) in variant_css {
+                    outputs.push((self.variant_output_path(root_index, module_id, name), css));
+                }
+                let mut write_errors = 0;
+                for (path, output) in outputs.iter() {
+                    if try_update_output_file(path, output) {
+                        self.manifest.add_artifact(module_id, path);
+                    } else {
+                        write_errors += 1;
                     }
                 }
+                if write_errors > 0 {
+                    // outputs should be written again by the next build
+                    self.manifest.remove(module_id);
+                    self.stats.failed += 1;
+                    self.stats.errors += write_errors;
+                } else {
+                    self.stats.compiled += 1;
+                }
             }
-            Err(err) => {
-                error!("Unable to read xiss file {:?}: {}", path, err);
+            Err(diagnostics) => {
+                self.css_map.rollback();
+                self.manifest.remove(module_id);
+                self.stats.failed += 1;
+                self.stats.errors += diagnostics.errors;
+                self.stats.warnings += diagnostics.warnings;
+                error!("Failed to compile {:?}\n{}", path, diagnostics);
             }
         }
+        Ok(())
     }
-    Ok(())
-}
 
-fn build<W: io::Write>(
-    modules: &mut FxHashSet<String>,
-    css_map: &mut CssMap,
-    css_map_writer: &mut W,
-    const_map: &FxHashMap<JsWord, Vec<ComponentValue>>,
-    output: &Path,
-    include: &Path,
-    force_update: bool,
-    class_map_output: ClassMapOutput,
-) -> eyre::Result<()> {
-    for entry in WalkDir::new(include) {
-        let entry = entry?;
-        if !has_module_extension(entry.path()) {
-            continue;
-        }
+    fn build(&mut self, force_update: bool) -> eyre::Result<()> {
+        for root_index in 0..self.roots.len() {
+            let root_path = self.roots[root_index].path.clone();
+            for entry in WalkDir::new(&root_path) {
+                let entry = entry?;
+                let path = entry.path();
+                if is_const_file(path) && find_root(&self.roots, path) == Some(root_index) {
+                    self.const_files.insert(path.to_path_buf(), root_index);
+                    continue;
+                }
+                // nested include roots are compiled separately
+                if !has_module_extension(path) || find_root(&self.roots, path) != Some(root_index) {
+                    continue;
+                }
 
-        match path_to_module_id(include, entry.path()) {
-            Ok(module_id) => {
-                modules.insert(module_id.to_string());
-                update_module(
-                    css_map,
-                    css_map_writer,
-                    const_map,
-                    output,
-                    entry.path(),
-                    module_id,
-                    force_update,
-                    class_map_output,
-                )?;
-            }
-            Err(err) => {
-                error!("{}", err);
+                self.add_module(root_index, path, force_update)?;
             }
         }
+        self.export_consts();
+
+        let modules = &self.modules;
+        self.manifest
+            .modules
+            .retain(|module_id, _| modules.contains_key(module_id));
+        self.write_manifest()
     }
-    Ok(())
-}
 
-#[derive(Debug)]
-enum WatchMessage {
-    FileChanged(PathBuf),
-    CtrlC,
-}
+    fn watch(&mut self, cwd: &Path) -> eyre::Result<()> {
+        use notify_debouncer_mini::{new_debouncer, notify::*, DebounceEventResult};
+        info!("Watching files for changes. Press Ctrl-C to abort...");
+
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let tx2 = tx.clone();
 
-fn watch<W: io::Write>(
-    modules: &mut FxHashSet<String>,
-    css_map: &mut CssMap,
-    css_map_writer: &mut W,
-    const_map: &FxHashMap<JsWord, Vec<ComponentValue>>,
-    cwd: &Path,
-    output: &Path,
-    include: &Path,
-    class_map_output: ClassMapOutput,
-) -> eyre::Result<()> {
-    use notify_debouncer_mini::{new_debouncer, notify::*, DebounceEventResult};
-    info!("Watching files for changes. Press Ctrl-C to abort...");
-
-    let (tx, rx) = crossbeam::channel::unbounded();
-    let tx2 = tx.clone();
-
-    let mut debouncer = new_debouncer(
-        Duration::from_millis(500),
-        None,
-        move |res: DebounceEventResult| match res {
-            Ok(events) => events.iter().for_each(|e| {
-                if has_module_extension(&e.path) {
+        let mut debouncer = new_debouncer(
+            Duration::from_millis(500),
+            None,
+            move |res: DebounceEventResult| match res {
+                // const files can have any extension, so all changes are sent
+                Ok(events) => events.iter().for_each(|e| {
                     tx.send(WatchMessage::FileChanged(e.path.to_path_buf()))
                         .expect("Could not send watcher message");
-                }
-            }),
-            Err(errors) => errors
-                .iter()
-                .for_each(|e| error!("File watcher error: {}", e)),
-        },
-    )?;
+                }),
+                Err(errors) => errors
+                    .iter()
+                    .for_each(|e| error!("File watcher error: {}", e)),
+            },
+        )?;
 
-    debouncer
-        .watcher()
-        .watch(Path::new(include), RecursiveMode::Recursive)?;
+        for root in self.roots.iter() {
+            debouncer
+                .watcher()
+                .watch(&root.path, RecursiveMode::Recursive)?;
+        }
+        // const files imported from directories outside of include roots
+        let mut watched_const_files = FxHashSet::default();
+        let mut watch_const_files =
+            |builder: &Self, watcher: &mut dyn Watcher| -> eyre::Result<()> {
+                for path in builder.consts.dependencies() {
+                    if find_root(&builder.roots, path).is_none()
+                        && path.is_file()
+                        && watched_const_files.insert(path.to_path_buf())
+                    {
+                        watcher.watch(path, RecursiveMode::NonRecursive)?;
+                    }
+                }
+                Ok(())
+            };
+        watch_const_files(self, debouncer.watcher())?;
 
-    ctrlc::set_handler(move || {
-        tx2.send(WatchMessage::CtrlC)
-            .expect("Could not send Ctrl-C signal")
-    })?;
+        ctrlc::set_handler(move || {
+            tx2.send(WatchMessage::CtrlC)
+                .expect("Could not send Ctrl-C signal")
+        })?;
 
-    loop {
-        match rx
-            .recv()
-            .wrap_err("Could not receive messages from a channel")?
-        {
-            WatchMessage::FileChanged(path) => {
-                if let Ok(path) = path.strip_prefix(cwd) {
-                    match path_to_module_id(include, path) {
-                        Ok(module_id) => {
+        loop {
+            match rx
+                .recv()
+                .wrap_err("Could not receive messages from a channel")?
+            {
+                WatchMessage::FileChanged(path) => {
+                    if let Ok(path) = path.strip_prefix(cwd) {
+                        if has_module_extension(path) {
+                            let root_index = if let Some(i) = find_root(&self.roots, path) {
+                                i
+                            } else {
+                                continue;
+                            };
                             if path.exists() {
-                                if !modules.contains(module_id) {
-                                    modules.insert(module_id.to_string());
-                                    trace!("File added: {:?}", path);
+                                self.add_module(root_index, path, false)?;
+                            } else if let Some(module_id) = self.module_ids.get(path) {
+                                let module_id = module_id.clone();
+                                self.remove_module(&module_id);
+                            }
+                        } else if self.consts.is_dependency(path) || is_const_file(path) {
+                            trace!("Const file changed: {:?}", path);
+                            if is_const_file(path) {
+                                if path.exists() {
+                                    if let Some(root_index) = find_root(&self.roots, path) {
+                                        self.const_files.insert(path.to_path_buf(), root_index);
+                                    }
                                 } else {
-                                    trace!("File modified: {:?}", path);
+                                    self.remove_const_exports(path);
                                 }
-                                update_module(
-                                    css_map,
-                                    css_map_writer,
-                                    const_map,
-                                    output,
-                                    &path,
-                                    module_id,
-                                    false,
-                                    class_map_output,
-                                )?;
-                            } else {
-                                modules.remove(module_id);
-
-                                let out_module_path = output.join(module_id);
-                                let css_path = out_module_path.with_extension("css");
-                                try_remove_file(&css_path);
-                                try_remove_file(&css_path.with_extension("js"));
-                                try_remove_file(&css_path.with_extension("d.ts"));
-
-                                trace!("File removed: {:?}", path);
                             }
+                            self.update_const_dependents(path)?;
+                            self.export_consts();
+                            watch_const_files(self, debouncer.watcher())?;
+                        } else {
+                            continue;
                         }
-                        Err(err) => {
-                            error!("{}", err);
-                        }
+                        self.write_manifest()?;
                     }
                 }
+                WatchMessage::CtrlC => break,
             }
-            WatchMessage::CtrlC => break,
         }
+
+        Ok(())
     }
 
-    Ok(())
-}
+    /// Recompiles modules that are using a changed const file.
+    fn update_const_dependents(&mut self, path: &Path) -> eyre::Result<()> {
+        self.consts.invalidate();
+        let dependents: Vec<(usize, PathBuf)> = self
+            .modules
+            .values()
+            .filter(|module| {
+                // modules with unresolved const values are retried
+                module
+                    .const_files
+                    .as_ref()
+                    .map_or(true, |files| files.iter().any(|p| p == path))
+            })
+            .map(|module| (module.root_index, module.path.clone()))
+            .collect();
+        for (root_index, path) in dependents {
+            self.add_module(root_index, &path, false)?;
+        }
+        Ok(())
+    }
 
-/// Purges output files that are no longer associated with xiss modules.
-fn purge_output_files(modules: &mut FxHashSet<String>, output: &Path) -> eyre::Result<()> {
-    trace!("Purging output files");
-    let mut purged_files = 0;
-    for entry in WalkDir::new(output) {
-        let entry = entry?;
-        let path = entry.path();
-        if let Some(file_name) = path.file_name() {
-            // ignore dot files
-            if !Path::new(file_name).starts_with(".") {
-                if path.is_file() {
-                    match path_to_module_id(output, path) {
-                        Ok(module_id) => {
-                            if !modules.contains(module_id) {
-                                trace!("Removing file {:?}", path);
-                                if let Err(err) = fs::remove_file(entry.path()) {
-                                    error!("Unable to remove file {:?}: {}", entry.path(), err);
-                                }
-                                modules.remove(module_id);
-                                purged_files += 1;
-                            }
-                        }
-                        Err(err) => {
-                            error!("{}", err);
-                        }
-                    }
+    /// Purges stale output files that were produced by previous builds.
+    ///
+    /// Only files that are recorded in the build manifest are removed.
+    fn purge_output_files(&mut self, dry_run: bool) -> eyre::Result<()> {
+        trace!("Purging output files");
+        let stale = self.manifest.stale_artifacts(|module_id, path| {
+            if let Some(const_file) = module_id.strip_prefix(CONST_ARTIFACT_PREFIX) {
+                let const_file = Path::new(const_file);
+                if let Some(root_index) = self.const_files.get(const_file) {
+                    self.const_output_paths(*root_index, const_file)
+                        .iter()
+                        .any(|p| p == path)
+                } else {
+                    false
                 }
+            } else if let Some(module) = self.modules.get(module_id) {
+                self.all_output_paths(module.root_index, module_id)
+                    .iter()
+                    .any(|p| p == path)
+            } else {
+                false
             }
+        });
+        let mut purged_files = 0;
+        for (module_id, path) in stale {
+            if dry_run {
+                info!("Stale file {:?}", path);
+                purged_files += 1;
+            } else if try_remove_file(&path) {
+                self.manifest.remove_artifact(&module_id, &path);
+                purged_files += 1;
+            }
+        }
+        if dry_run {
+            info!("{} files would be purged", purged_files);
+        } else if purged_files > 0 {
+            info!("Purged {} files", purged_files);
+            self.write_manifest()?;
         }
+        Ok(())
     }
-    if purged_files > 0 {
-        info!("Purged {} files", purged_files);
+
+    /// Returns paths to css, js and d.ts output files.
+    fn output_paths(&self, root_index: usize, module_id: &str) -> [PathBuf; 3] {
+        let css_path = self.roots[root_index]
+            .output_path(module_id)
+            .with_extension("css");
+        let js_path = css_path.with_extension("js");
+        let ts_path = css_path.with_extension("d.ts");
+        [css_path, js_path, ts_path]
+    }
+
+    /// Exports const values from `const.css` files to `const.js`,
+    /// `const.d.ts` and `const.json` files.
+    fn export_consts(&mut self) {
+        let mut const_files: Vec<(PathBuf, usize)> = self
+            .const_files
+            .iter()
+            .map(|(path, root_index)| (path.clone(), *root_index))
+            .collect();
+        const_files.sort();
+        for (path, root_index) in const_files {
+            let artifact_key = const_artifact_key(&path);
+            let output_paths = self.const_output_paths(root_index, &path);
+            let output_dir = output_paths[0].parent().unwrap_or(Path::new(""));
+            if let Some((module_id, _)) = self.modules.iter().find(|(module_id, module)| {
+                self.roots[module.root_index].output_path(module_id) == output_dir.join("const")
+            }) {
+                self.stats.errors += 1;
+                error!(
+                    "Unable to export const file {:?}, output files are used by module \"{}\"",
+                    path, module_id
+                );
+                continue;
+            }
+            let dir = path.parent().unwrap_or(Path::new(""));
+            let exports = self
+                .consts
+                .resolve(&self.roots[root_index].path, dir)
+                .and_then(|consts| export_consts(&consts.values));
+            let exports = match exports {
+                Ok(exports) => exports,
+                Err(err) => {
+                    self.stats.errors += 1;
+                    error!("Failed to export const file {:?}\n{}", path, err);
+                    continue;
+                }
+            };
+            if !output_dir.exists() {
+                if let Err(err) = fs::create_dir_all(output_dir) {
+                    error!(
+                        "Unable to create output directory {:?}: {}",
+                        output_dir, err
+                    );
+                }
+            }
+            for (output_path, output) in
+                output_paths
+                    .iter()
+                    .zip([&exports.js, &exports.ts, &exports.json])
+            {
+                if try_update_output_file(output_path, output) {
+                    self.manifest.add_artifact(&artifact_key, output_path);
+                } else {
+                    self.stats.errors += 1;
+                }
+            }
+        }
+    }
+
+    /// Removes files exported from a removed const file.
+    fn remove_const_exports(&mut self, path: &Path) {
+        if let Some(root_index) = self.const_files.remove(path) {
+            let artifact_key = const_artifact_key(path);
+            for output_path in self.const_output_paths(root_index, path) {
+                if try_remove_file(&output_path) {
+                    self.manifest.remove_artifact(&artifact_key, &output_path);
+                }
+            }
+        }
+    }
+
+    /// Returns paths to js, d.ts and optional json files exported from a
+    /// const file.
+    fn const_output_paths(&self, root_index: usize, path: &Path) -> Vec<PathBuf> {
+        let root = &self.roots[root_index];
+        let dir = path
+            .parent()
+            .and_then(|dir| dir.strip_prefix(&root.path).ok())
+            .unwrap_or(Path::new(""));
+        let base = root.output.join(dir).join("const");
+        let mut paths = vec![base.with_extension("js"), base.with_extension("d.ts")];
+        if self.const_json {
+            paths.push(base.with_extension("json"));
+        }
+        paths
+    }
+
+    /// Returns a path to a css output file for a build variant.
+    fn variant_output_path(&self, root_index: usize, module_id: &str, variant: &str) -> PathBuf {
+        self.roots[root_index]
+            .output_path(module_id)
+            .with_extension(format!("{}.css", variant))
     }
-    Ok(())
-}
 
-/// Returns true if path has a [MODULE_EXTENSION].
-fn has_module_extension(path: &Path) -> bool {
-    if let Some(ext) = path.extension() {
-        if ext == MODULE_EXTENSION {
-            return true;
+    /// Returns a path to a `.d.ts.map` declaration map.
+    fn declaration_map_path(&self, root_index: usize, module_id: &str) -> PathBuf {
+        self.roots[root_index]
+            .output_path(module_id)
+            .with_extension("d.ts.map")
+    }
+
+    /// Returns paths to all output files, including css files for build
+    /// variants and declaration maps.
+    fn all_output_paths(&self, root_index: usize, module_id: &str) -> Vec<PathBuf> {
+        let mut paths = self.output_paths(root_index, module_id).to_vec();
+        for (name, _) in self.variants.iter() {
+            paths.push(self.variant_output_path(root_index, module_id, name));
+        }
+        if self.compiler_options.declaration_map {
+            paths.push(self.declaration_map_path(root_index, module_id));
         }
+        paths
+    }
+
+    fn write_manifest(&self) -> eyre::Result<()> {
+        self.manifest
+            .write(&self.manifest_path)
+            .wrap_err_with(|| format!("Failed to write build manifest {:?}", self.manifest_path))
     }
-    false
 }
 
-/// Extracts module id from a path.
-fn path_to_module_id<'a>(output: &Path, path: &'a Path) -> eyre::Result<&'a str> {
-    let relative_path = path.strip_prefix(output).wrap_err_with(|| {
-        format!(
-            "Invalid file path {:?}, path should be a subpath of an output directory {:?}",
-            path, output
-        )
-    })?;
-    let mut module_id = if let Some(path) = relative_path.to_str() {
-        path
-    } else {
-        return Err(eyre::eyre!(
-            "Invalid file path {:?}, path should be a valid utf8 string",
-            relative_path,
-        ));
-    };
+/// [BuildStats] counts compilation results.
+#[derive(Debug, Default)]
+struct BuildStats {
+    /// Number of compiled modules.
+    compiled: usize,
+    /// Number of modules that failed to compile.
+    failed: usize,
+    errors: usize,
+    warnings: usize,
+}
 
-    while let Some(i) = module_id.rfind('.') {
-        module_id = &module_id[..i];
+impl BuildStats {
+    fn is_failed(&self, deny_warnings: bool) -> bool {
+        self.failed > 0 || self.errors > 0 || (deny_warnings && self.warnings > 0)
     }
-    Ok(module_id)
+
+    /// Prints a build summary.
+    fn report(&self) {
+        if self.failed > 0 || self.errors > 0 {
+            error!(
+                "Build failed: {} modules compiled, {} modules failed ({} errors, {} warnings)",
+                self.compiled, self.failed, self.errors, self.warnings
+            );
+        } else if self.warnings > 0 {
+            warn!(
+                "Build finished: {} modules compiled ({} warnings)",
+                self.compiled, self.warnings
+            );
+        } else if self.compiled > 0 {
+            info!("Build finished: {} modules compiled", self.compiled);
+        }
+    }
+}
+
+#[derive(Debug)]
+enum WatchMessage {
+    FileChanged(PathBuf),
+    CtrlC,
 }
 
 /// Checks if output file should be updated.
@@ -470,54 +975,46 @@ fn should_update(path: &Path, content: &str) -> bool {
     }
 }
 
-/// Checks if module should be recompiled.
-fn should_compile(module_path: &Path, css_path: &Path, js_path: &Path, ts_path: &Path) -> bool {
-    if let Ok(output_time) = max_modified_time_3(css_path, js_path, ts_path) {
-        if let Ok(meta) = module_path.metadata() {
-            if let Ok(input_time) = meta.modified() {
-                if input_time <= output_time {
-                    return false;
-                }
-            }
+/// Updates output file, returns false when file can't be written.
+fn try_update_output_file(path: &Path, output: &str) -> bool {
+    if should_update(path, output) {
+        trace!("Updating {:?}", path);
+        if let Err(err) = write_atomic(path, output) {
+            error!("Unable to write file {:?}: {}", path, err);
+            return false;
         }
     }
     true
 }
 
-/// Returns max modified time.
-fn max_modified_time_3(
-    css_path: &Path,
-    js_path: &Path,
-    ts_path: &Path,
-) -> Result<SystemTime, std::io::Error> {
-    let mut t = css_path.metadata()?.modified()?;
-    let t2 = js_path.metadata()?.modified()?;
-    if t2 > t {
-        t = t2;
-    }
-    let t3 = ts_path.metadata()?.modified()?;
-    if t3 > t {
-        t = t3;
-    }
+/// Returns a file name as a string.
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
 
-    Ok(t)
+/// Manifest artifacts exported from const files are recorded with a
+/// `const:` prefix and a const file path.
+const CONST_ARTIFACT_PREFIX: &str = "const:";
+
+fn const_artifact_key(path: &Path) -> String {
+    format!("{}{}", CONST_ARTIFACT_PREFIX, path.display())
 }
 
-fn try_update_output_file(path: &Path, output: &str) {
-    if should_update(path, output) {
-        trace!("Updating {:?}", path);
-        if let Err(err) = fs::write(path, output) {
-            error!("Unable to write file {:?}: {}", path, err);
-        }
-    }
+fn is_const_file(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name == CONST_FILE_NAME)
 }
 
-fn try_remove_file(path: &Path) {
+/// Removes file, returns false when existing file can't be removed.
+fn try_remove_file(path: &Path) -> bool {
     if path.exists() {
         if let Err(err) = fs::remove_file(path) {
             error!("Unable to remove file {:?}: {}", path, err);
-        } else {
-            trace!("Removing file {:?}", path);
+            return false;
         }
+        trace!("Removing file {:?}", path);
     }
+    true
 }
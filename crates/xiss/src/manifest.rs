@@ -0,0 +1,475 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    hash::{Hash, Hasher},
+    io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use rustc_hash::FxHasher;
+use serde::{Deserialize, Serialize};
+use swc_atoms::JsWord;
+use swc_css::ast::ComponentValue;
+use xiss_map::IdKind;
+
+use crate::{
+    compiler::CompilationArtifact, const_map::ResolvedConsts, css::serialize_component_values,
+    css_map::CssMap, mixin::Mixin, output::write_atomic, util::hash,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ManifestError {
+    #[error(transparent)]
+    IOError(#[from] io::Error),
+    #[error(transparent)]
+    JsonError(#[from] serde_json::Error),
+}
+
+/// [Manifest] stores fingerprints of all inputs that were used to compile
+/// modules.
+///
+/// Module should be recompiled when any of its fingerprints doesn't match
+/// the current state.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Fingerprints are valid only for the same [manifest_version].
+    #[serde(default)]
+    pub version: String,
+    pub modules: BTreeMap<String, ManifestModule>,
+    /// Output files written for each module.
+    ///
+    /// Artifacts are tracked separately from module fingerprints, so that
+    /// outputs of modules that failed to compile can still be purged.
+    #[serde(default)]
+    pub artifacts: BTreeMap<String, BTreeSet<PathBuf>>,
+}
+
+impl Default for Manifest {
+    fn default() -> Self {
+        Self {
+            version: manifest_version(),
+            modules: BTreeMap::new(),
+            artifacts: BTreeMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestModule {
+    /// Hash of the module source.
+    pub source: u64,
+    /// Hash of the compiler options.
+    pub options: u64,
+    /// Hashes of the const values used by the module.
+    pub consts: BTreeMap<String, u64>,
+    /// Hashes of the custom media queries used by the module.
+    #[serde(default)]
+    pub custom_media: BTreeMap<String, u64>,
+    /// Hashes of the global mixins used by the module.
+    #[serde(default)]
+    pub mixins: BTreeMap<String, u64>,
+    /// Hashes of the const values, custom media queries and mixins used by
+    /// the module in each build variant.
+    #[serde(default)]
+    pub variants: BTreeMap<String, u64>,
+    /// IDs resolved by the module.
+    pub ids: Vec<ManifestId>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestId {
+    pub kind: char,
+    pub module: String,
+    pub local: String,
+    pub global: String,
+}
+
+/// [ModuleInputs] are inputs that are used to compile a module.
+pub struct ModuleInputs<'a> {
+    pub module_id: &'a str,
+    /// Hash of the module source.
+    pub source_hash: u64,
+    /// Hash of the compiler options.
+    pub options_hash: u64,
+    pub consts: &'a ResolvedConsts,
+    /// Build variants with their const values.
+    pub variants: &'a [(&'a str, &'a ResolvedConsts)],
+}
+
+impl Manifest {
+    /// Loads manifest from a file.
+    ///
+    /// Fingerprints from manifests with a different version are discarded,
+    /// artifacts are preserved, so that outputs can still be purged.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Manifest, ManifestError> {
+        let contents = fs::read_to_string(path)?;
+        let mut manifest: Manifest = serde_json::from_str(&contents)?;
+        let version = manifest_version();
+        if manifest.version != version {
+            manifest.version = version;
+            manifest.modules.clear();
+        }
+        Ok(manifest)
+    }
+
+    /// Writes manifest into a file.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<(), ManifestError> {
+        write_atomic(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Checks if module compiled with the current inputs would produce the
+    /// same output as the last time.
+    pub fn is_fresh(&self, inputs: &ModuleInputs, css_map: &CssMap) -> bool {
+        let module = if let Some(module) = self.modules.get(inputs.module_id) {
+            module
+        } else {
+            return false;
+        };
+        if module.source != inputs.source_hash || module.options != inputs.options_hash {
+            return false;
+        }
+        for (name, hash) in module.consts.iter() {
+            if const_value_hash(inputs.consts.values.get(&JsWord::from(name.as_str()))) != *hash {
+                return false;
+            }
+        }
+        for (name, hash) in module.custom_media.iter() {
+            let query = inputs.consts.custom_media.get(&JsWord::from(name.as_str()));
+            if custom_media_hash(query) != *hash {
+                return false;
+            }
+        }
+        for (name, hash) in module.mixins.iter() {
+            if mixin_hash(inputs.consts.mixins.get(&JsWord::from(name.as_str()))) != *hash {
+                return false;
+            }
+        }
+        if module.variants.len() != inputs.variants.len() {
+            return false;
+        }
+        for (name, consts) in inputs.variants {
+            if module.variants.get(*name) != Some(&variant_hash(module, consts)) {
+                return false;
+            }
+        }
+        for id in module.ids.iter() {
+            let kind = if let Some(kind) = IdKind::from_char(id.kind) {
+                kind
+            } else {
+                return false;
+            };
+            match css_map.find_id(&id.module, kind, &id.local) {
+                Some(resolved) if resolved.global_id == id.global => {}
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    /// Updates module fingerprints after a successful compilation.
+    pub fn update(
+        &mut self,
+        inputs: &ModuleInputs,
+        css_map: &CssMap,
+        artifact: &CompilationArtifact,
+    ) {
+        let custom_media = artifact
+            .custom_media
+            .iter()
+            .map(|name| {
+                (
+                    name.to_string(),
+                    custom_media_hash(inputs.consts.custom_media.get(name)),
+                )
+            })
+            .collect();
+        let mixins = artifact
+            .mixins
+            .iter()
+            .map(|name| (name.to_string(), mixin_hash(inputs.consts.mixins.get(name))))
+            .collect();
+        let consts = artifact
+            .consts
+            .iter()
+            .map(|name| {
+                (
+                    name.to_string(),
+                    const_value_hash(inputs.consts.values.get(name)),
+                )
+            })
+            .collect();
+        let ids = artifact
+            .ids
+            .iter()
+            .map(|id| ManifestId {
+                kind: id.kind.to_char(),
+                module: css_map.modules[id.module_index as usize].id.to_string(),
+                local: id.local_id.to_string(),
+                global: id.global_id.to_string(),
+            })
+            .collect();
+        let mut module = ManifestModule {
+            source: inputs.source_hash,
+            options: inputs.options_hash,
+            consts,
+            custom_media,
+            mixins,
+            variants: BTreeMap::new(),
+            ids,
+        };
+        module.variants = inputs
+            .variants
+            .iter()
+            .map(|(name, consts)| (name.to_string(), variant_hash(&module, consts)))
+            .collect();
+        self.modules.insert(inputs.module_id.to_string(), module);
+    }
+
+    /// Removes module fingerprints.
+    pub fn remove(&mut self, module_id: &str) {
+        self.modules.remove(module_id);
+    }
+
+    /// Records an output file written for a module.
+    pub fn add_artifact(&mut self, module_id: &str, path: &Path) {
+        self.artifacts
+            .entry(module_id.to_string())
+            .or_default()
+            .insert(path.to_path_buf());
+    }
+
+    /// Removes an output file from module artifacts.
+    pub fn remove_artifact(&mut self, module_id: &str, path: &Path) {
+        if let Some(artifacts) = self.artifacts.get_mut(module_id) {
+            artifacts.remove(path);
+            if artifacts.is_empty() {
+                self.artifacts.remove(module_id);
+            }
+        }
+    }
+
+    /// Returns artifacts that aren't produced by the current build.
+    pub fn stale_artifacts<F: Fn(&str, &Path) -> bool>(
+        &self,
+        is_current: F,
+    ) -> Vec<(String, PathBuf)> {
+        let mut result = Vec::new();
+        for (module_id, artifacts) in self.artifacts.iter() {
+            for path in artifacts.iter() {
+                if !is_current(module_id, path) {
+                    result.push((module_id.clone(), path.clone()));
+                }
+            }
+        }
+        result
+    }
+}
+
+/// Returns a manifest version.
+///
+/// Hashes depend on the hasher, `Hash` implementations from the standard
+/// library and the target platform, so manifest version includes xiss and
+/// rustc versions, target and a hash of sample values.
+pub fn manifest_version() -> String {
+    let sample = hash(&(
+        "xiss",
+        1u64,
+        usize::MAX,
+        Some(true),
+        vec![PathBuf::from("a/b")],
+        BTreeMap::from([("a", 'b')]),
+    ));
+    format!(
+        "{} ({}; {}; {:016x})",
+        env!("CARGO_PKG_VERSION"),
+        env!("XISS_RUSTC_VERSION"),
+        env!("XISS_TARGET"),
+        sample
+    )
+}
+
+/// Returns a hash of const values, custom media queries and mixins that are
+/// used by a module in a build variant.
+fn variant_hash(module: &ManifestModule, consts: &ResolvedConsts) -> u64 {
+    let mut hasher = FxHasher::default();
+    for name in module.consts.keys() {
+        name.hash(&mut hasher);
+        const_value_hash(consts.values.get(&JsWord::from(name.as_str()))).hash(&mut hasher);
+    }
+    for name in module.custom_media.keys() {
+        name.hash(&mut hasher);
+        custom_media_hash(consts.custom_media.get(&JsWord::from(name.as_str()))).hash(&mut hasher);
+    }
+    for name in module.mixins.keys() {
+        name.hash(&mut hasher);
+        mixin_hash(consts.mixins.get(&JsWord::from(name.as_str()))).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+/// Returns a hash of a mixin, missing mixins are hashed as zero.
+fn mixin_hash(mixin: Option<&Rc<Mixin>>) -> u64 {
+    mixin.map_or(0, |mixin| mixin.hash)
+}
+
+/// Returns a hash of a custom media query, missing queries are hashed as zero.
+fn custom_media_hash(query: Option<&String>) -> u64 {
+    if let Some(query) = query {
+        hash(query)
+    } else {
+        0
+    }
+}
+
+/// Returns a hash of a const value, missing values are hashed as zero.
+fn const_value_hash(value: Option<&Vec<ComponentValue>>) -> u64 {
+    if let Some(value) = value {
+        hash(&serialize_component_values(value))
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn artifact(css_map: &mut CssMap) -> CompilationArtifact {
+        let module_index = css_map.get_module_index("m");
+        let id = css_map.get_id(module_index, IdKind::Class, "Button");
+        CompilationArtifact {
+            css: String::new(),
+            js: String::new(),
+            ts: String::new(),
+            ts_mappings: vec![],
+            consts: vec![],
+            custom_media: vec![],
+            mixins: vec![],
+            ids: vec![id],
+            diagnostics: Default::default(),
+        }
+    }
+
+    fn inputs(source_hash: u64, options_hash: u64, consts: &ResolvedConsts) -> ModuleInputs {
+        ModuleInputs {
+            module_id: "m",
+            source_hash,
+            options_hash,
+            consts,
+            variants: &[],
+        }
+    }
+
+    #[test]
+    fn missing_module() {
+        let css_map = CssMap::new(&vec![], &vec![], &vec![]).unwrap();
+        let manifest = Manifest::default();
+        assert!(!manifest.is_fresh(&inputs(1, 1, &ResolvedConsts::default()), &css_map));
+    }
+
+    #[test]
+    fn fresh_module() {
+        let mut css_map = CssMap::new(&vec![], &vec![], &vec![]).unwrap();
+        let artifact = artifact(&mut css_map);
+        let consts = ResolvedConsts::default();
+        let mut manifest = Manifest::default();
+        manifest.update(&inputs(1, 1, &consts), &css_map, &artifact);
+        assert!(manifest.is_fresh(&inputs(1, 1, &consts), &css_map));
+    }
+
+    #[test]
+    fn changed_source() {
+        let mut css_map = CssMap::new(&vec![], &vec![], &vec![]).unwrap();
+        let artifact = artifact(&mut css_map);
+        let consts = ResolvedConsts::default();
+        let mut manifest = Manifest::default();
+        manifest.update(&inputs(1, 1, &consts), &css_map, &artifact);
+        assert!(!manifest.is_fresh(&inputs(2, 1, &consts), &css_map));
+        assert!(!manifest.is_fresh(&inputs(1, 2, &consts), &css_map));
+    }
+
+    #[test]
+    fn changed_version() {
+        let mut css_map = CssMap::new(&vec![], &vec![], &vec![]).unwrap();
+        let artifact = artifact(&mut css_map);
+        let consts = ResolvedConsts::default();
+        let mut manifest = Manifest::default();
+        manifest.update(&inputs(1, 1, &consts), &css_map, &artifact);
+        manifest.add_artifact("m", Path::new("m.css"));
+
+        let path = std::env::temp_dir().join(format!("xiss-manifest-{}.json", std::process::id()));
+        manifest.write(&path).unwrap();
+        let loaded = Manifest::from_file(&path).unwrap();
+        assert!(loaded.is_fresh(&inputs(1, 1, &consts), &css_map));
+
+        manifest.version = "0.0.0".to_string();
+        manifest.write(&path).unwrap();
+        let loaded = Manifest::from_file(&path).unwrap();
+        assert_eq!(loaded.version, manifest_version());
+        assert!(!loaded.is_fresh(&inputs(1, 1, &consts), &css_map));
+        assert!(loaded.artifacts["m"].contains(Path::new("m.css")));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn changed_css_map() {
+        let mut css_map = CssMap::new(&vec![], &vec![], &vec![]).unwrap();
+        let artifact = artifact(&mut css_map);
+        let consts = ResolvedConsts::default();
+        let mut manifest = Manifest::default();
+        manifest.update(&inputs(1, 1, &consts), &css_map, &artifact);
+
+        let css_map = CssMap::new(&vec![], &vec![], &vec![]).unwrap();
+        assert!(!manifest.is_fresh(&inputs(1, 1, &consts), &css_map));
+    }
+
+    #[test]
+    fn changed_variant_consts() {
+        let mut css_map = CssMap::new(&vec![], &vec![], &vec![]).unwrap();
+        let mut artifact = artifact(&mut css_map);
+        artifact.consts = vec!["--A".into()];
+        let consts = ResolvedConsts::default();
+        let mut brand = ResolvedConsts::default();
+        brand.values.insert("--A".into(), vec![]);
+        let brand_variants = [("brand", &brand)];
+        let brand_inputs = ModuleInputs {
+            variants: &brand_variants,
+            ..inputs(1, 1, &consts)
+        };
+        let mut manifest = Manifest::default();
+        manifest.update(&brand_inputs, &css_map, &artifact);
+        assert!(manifest.is_fresh(&brand_inputs, &css_map));
+        let changed_variants = [("brand", &consts)];
+        let changed_inputs = ModuleInputs {
+            variants: &changed_variants,
+            ..inputs(1, 1, &consts)
+        };
+        assert!(!manifest.is_fresh(&changed_inputs, &css_map));
+        assert!(!manifest.is_fresh(&inputs(1, 1, &consts), &css_map));
+    }
+
+    #[test]
+    fn stale_artifacts() {
+        let mut manifest = Manifest::default();
+        manifest.add_artifact("a", Path::new("build/a.css"));
+        manifest.add_artifact("a", Path::new("build/a.js"));
+        manifest.add_artifact("b", Path::new("build/b.css"));
+        manifest.remove("b");
+
+        let stale = manifest
+            .stale_artifacts(|module_id, path| module_id == "a" && path != Path::new("build/a.js"));
+        assert_eq!(
+            stale,
+            vec![
+                ("a".to_string(), PathBuf::from("build/a.js")),
+                ("b".to_string(), PathBuf::from("build/b.css")),
+            ]
+        );
+
+        manifest.remove_artifact("b", Path::new("build/b.css"));
+        assert!(!manifest.artifacts.contains_key("b"));
+    }
+}
@@ -0,0 +1,949 @@
+use std::{mem::take, rc::Rc};
+
+use phf::phf_set;
+use rustc_hash::{FxHashMap, FxHashSet};
+use swc_atoms::JsWord;
+use swc_common::{errors::HANDLER, Span, Spanned};
+use swc_css::{
+    ast::*,
+    visit::{VisitMut, VisitMutWith},
+};
+
+use crate::{
+    css::{
+        parse_declaration_list, parse_declaration_value, serialize_at_rule_prelude,
+        serialize_component_values, ReplaceSpan,
+    },
+    util::hash,
+};
+
+/// CSS named colors, `currentcolor` and `transparent` keywords.
+static COLOR_KEYWORDS: phf::Set<&'static str> = phf_set! {
+    "aliceblue",
+    "antiquewhite",
+    "aqua",
+    "aquamarine",
+    "azure",
+    "beige",
+    "bisque",
+    "black",
+    "blanchedalmond",
+    "blue",
+    "blueviolet",
+    "brown",
+    "burlywood",
+    "cadetblue",
+    "chartreuse",
+    "chocolate",
+    "coral",
+    "cornflowerblue",
+    "cornsilk",
+    "crimson",
+    "cyan",
+    "darkblue",
+    "darkcyan",
+    "darkgoldenrod",
+    "darkgray",
+    "darkgreen",
+    "darkgrey",
+    "darkkhaki",
+    "darkmagenta",
+    "darkolivegreen",
+    "darkorange",
+    "darkorchid",
+    "darkred",
+    "darksalmon",
+    "darkseagreen",
+    "darkslateblue",
+    "darkslategray",
+    "darkslategrey",
+    "darkturquoise",
+    "darkviolet",
+    "deeppink",
+    "deepskyblue",
+    "dimgray",
+    "dimgrey",
+    "dodgerblue",
+    "firebrick",
+    "floralwhite",
+    "forestgreen",
+    "fuchsia",
+    "gainsboro",
+    "ghostwhite",
+    "gold",
+    "goldenrod",
+    "gray",
+    "green",
+    "greenyellow",
+    "grey",
+    "honeydew",
+    "hotpink",
+    "indianred",
+    "indigo",
+    "ivory",
+    "khaki",
+    "lavender",
+    "lavenderblush",
+    "lawngreen",
+    "lemonchiffon",
+    "lightblue",
+    "lightcoral",
+    "lightcyan",
+    "lightgoldenrodyellow",
+    "lightgray",
+    "lightgreen",
+    "lightgrey",
+    "lightpink",
+    "lightsalmon",
+    "lightseagreen",
+    "lightskyblue",
+    "lightslategray",
+    "lightslategrey",
+    "lightsteelblue",
+    "lightyellow",
+    "lime",
+    "limegreen",
+    "linen",
+    "magenta",
+    "maroon",
+    "mediumaquamarine",
+    "mediumblue",
+    "mediumorchid",
+    "mediumpurple",
+    "mediumseagreen",
+    "mediumslateblue",
+    "mediumspringgreen",
+    "mediumturquoise",
+    "mediumvioletred",
+    "midnightblue",
+    "mintcream",
+    "mistyrose",
+    "moccasin",
+    "navajowhite",
+    "navy",
+    "oldlace",
+    "olive",
+    "olivedrab",
+    "orange",
+    "orangered",
+    "orchid",
+    "palegoldenrod",
+    "palegreen",
+    "paleturquoise",
+    "palevioletred",
+    "papayawhip",
+    "peachpuff",
+    "peru",
+    "pink",
+    "plum",
+    "powderblue",
+    "purple",
+    "rebeccapurple",
+    "red",
+    "rosybrown",
+    "royalblue",
+    "saddlebrown",
+    "salmon",
+    "sandybrown",
+    "seagreen",
+    "seashell",
+    "sienna",
+    "silver",
+    "skyblue",
+    "slateblue",
+    "slategray",
+    "slategrey",
+    "snow",
+    "springgreen",
+    "steelblue",
+    "tan",
+    "teal",
+    "thistle",
+    "tomato",
+    "turquoise",
+    "violet",
+    "wheat",
+    "white",
+    "whitesmoke",
+    "yellow",
+    "yellowgreen",
+    "currentcolor",
+    "transparent",
+};
+
+/// Mixin parameter types, e.g. `$width <length>`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MixinParamType {
+    Length,
+    Number,
+    Percentage,
+    Time,
+    Angle,
+    Color,
+    Ident,
+    String,
+}
+
+impl MixinParamType {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "length" => Some(Self::Length),
+            "number" => Some(Self::Number),
+            "percentage" => Some(Self::Percentage),
+            "time" => Some(Self::Time),
+            "angle" => Some(Self::Angle),
+            "color" => Some(Self::Color),
+            "ident" => Some(Self::Ident),
+            "string" => Some(Self::String),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Length => "length",
+            Self::Number => "number",
+            Self::Percentage => "percentage",
+            Self::Time => "time",
+            Self::Angle => "angle",
+            Self::Color => "color",
+            Self::Ident => "ident",
+            Self::String => "string",
+        }
+    }
+
+    /// Checks if a serialized value matches the type.
+    ///
+    /// Values with functions that are resolved later (e.g. `var()`) can't be
+    /// checked and are always matching.
+    fn matches(&self, value: &str) -> bool {
+        let value = value.trim();
+        if ["var(", "const(", "env(", "calc(", "min(", "max(", "clamp("]
+            .iter()
+            .any(|f| value.starts_with(f))
+        {
+            return true;
+        }
+        match self {
+            Self::Length => {
+                value == "0"
+                    || has_unit(
+                        value,
+                        &[
+                            "px", "em", "rem", "ex", "rex", "ch", "rch", "cap", "rcap", "ic",
+                            "ric", "lh", "rlh", "vw", "vh", "vi", "vb", "vmin", "vmax", "svw",
+                            "svh", "svi", "svb", "svmin", "svmax", "lvw", "lvh", "lvi", "lvb",
+                            "lvmin", "lvmax", "dvw", "dvh", "dvi", "dvb", "dvmin", "dvmax", "cqw",
+                            "cqh", "cqi", "cqb", "cqmin", "cqmax", "cm", "mm", "in", "pt", "pc",
+                            "q",
+                        ],
+                    )
+            }
+            Self::Number => value.parse::<f64>().is_ok(),
+            Self::Percentage => has_unit(value, &["%"]),
+            Self::Time => has_unit(value, &["ms", "s"]),
+            Self::Angle => has_unit(value, &["deg", "rad", "grad", "turn"]),
+            Self::Color => {
+                value.starts_with('#')
+                    || [
+                        "rgb(",
+                        "rgba(",
+                        "hsl(",
+                        "hsla(",
+                        "hwb(",
+                        "lab(",
+                        "lch(",
+                        "oklab(",
+                        "oklch(",
+                        "color(",
+                        "color-mix(",
+                    ]
+                    .iter()
+                    .any(|f| value.starts_with(f))
+                    || COLOR_KEYWORDS.contains(value.to_ascii_lowercase().as_str())
+            }
+            Self::Ident => {
+                !value.is_empty()
+                    && !value.starts_with(|c: char| c.is_ascii_digit())
+                    && value
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            }
+            Self::String => {
+                value.len() >= 2
+                    && ((value.starts_with('"') && value.ends_with('"'))
+                        || (value.starts_with('\'') && value.ends_with('\'')))
+            }
+        }
+    }
+}
+
+fn has_unit(value: &str, units: &[&str]) -> bool {
+    units.iter().any(|unit| {
+        value
+            .strip_suffix(unit)
+            .is_some_and(|n| !n.is_empty() && n.parse::<f64>().is_ok())
+    })
+}
+
+#[derive(Debug)]
+pub struct MixinParam {
+    pub name: JsWord,
+    pub ty: Option<MixinParamType>,
+    pub default: Option<Vec<ComponentValue>>,
+}
+
+/// [Mixin] is a group of declarations declared with a `@mixin` rule.
+#[derive(Debug)]
+pub struct Mixin {
+    pub params: Vec<MixinParam>,
+    /// Tokens of declarations and nested rules.
+    pub body: Vec<ComponentValue>,
+    /// Hash of the mixin source, it is used to detect changes in mixins
+    /// declared in const files.
+    pub hash: u64,
+}
+
+/// Parses `@mixin name($a <type>, $b: default) { ... }` rule.
+pub fn parse_mixin(at_rule: &AtRule) -> Result<(JsWord, Mixin), (Span, String)> {
+    let (name, args) = parse_call(at_rule)?;
+    let mut params: Vec<MixinParam> = Vec::with_capacity(args.len());
+    for arg in args {
+        let param = parse_param(&arg)?;
+        if params.iter().any(|p| p.name == param.name) {
+            return Err((
+                group_span(&arg),
+                format!("Duplicate mixin parameter '${}'", param.name),
+            ));
+        }
+        if param.default.is_none() && params.iter().any(|p| p.default.is_some()) {
+            return Err((
+                group_span(&arg),
+                format!(
+                    "Mixin parameter '${}' should have a default value, parameters with default \
+                     values should be declared after required parameters",
+                    param.name
+                ),
+            ));
+        }
+        params.push(param);
+    }
+    let block = at_rule
+        .block
+        .as_ref()
+        .ok_or_else(|| (at_rule.span, "Expected mixin block".to_string()))?;
+    // block of an unknown at-rule is parsed as a list of tokens, declarations
+    // are parsed when parameters are substituted
+    let body = block.value.clone();
+    let body_source = serialize_component_values(&block.value);
+    let prelude = at_rule
+        .prelude
+        .as_deref()
+        .map(serialize_at_rule_prelude)
+        .unwrap_or_default();
+    Ok((
+        name,
+        Mixin {
+            params,
+            body,
+            hash: hash(&(prelude, body_source)),
+        },
+    ))
+}
+
+/// Tokens of a mixin argument.
+type MixinArg = Vec<ComponentValue>;
+
+/// Parses `name` or `name(args)` prelude, returns a name and arguments.
+fn parse_call(at_rule: &AtRule) -> Result<(JsWord, Vec<MixinArg>), (Span, String)> {
+    let invalid = || {
+        (
+            at_rule.span,
+            "Invalid mixin rule, expected a mixin name with optional arguments, e.g. 'name' or \
+             'name($a, $b)'"
+                .to_string(),
+        )
+    };
+    let values = match at_rule.prelude.as_deref() {
+        Some(AtRulePrelude::ListOfComponentValues(values)) => values,
+        _ => return Err(invalid()),
+    };
+    let mut iter = values.children.iter().filter(|v| !is_whitespace(v));
+    let result = match iter.next() {
+        Some(ComponentValue::PreservedToken(token_and_span)) => match &token_and_span.token {
+            Token::Ident { value, .. } => (value.clone(), Vec::new()),
+            _ => return Err(invalid()),
+        },
+        Some(ComponentValue::Function(func)) => match &func.name {
+            FunctionName::Ident(ident) => (ident.value.clone(), split_args(&func.value)),
+            _ => return Err(invalid()),
+        },
+        _ => return Err(invalid()),
+    };
+    if iter.next().is_some() {
+        return Err(invalid());
+    }
+    Ok(result)
+}
+
+/// Splits function arguments separated with commas.
+fn split_args(values: &[ComponentValue]) -> Vec<MixinArg> {
+    let mut result = Vec::new();
+    let mut arg = Vec::new();
+    for v in values {
+        if let ComponentValue::PreservedToken(token_and_span) = v {
+            if let Token::Comma = token_and_span.token {
+                result.push(trim_whitespace(arg));
+                arg = Vec::new();
+                continue;
+            }
+        }
+        arg.push(v.clone());
+    }
+    let arg = trim_whitespace(arg);
+    if !arg.is_empty() || !result.is_empty() {
+        result.push(arg);
+    }
+    result
+}
+
+fn trim_whitespace(mut values: Vec<ComponentValue>) -> Vec<ComponentValue> {
+    while values.last().is_some_and(is_whitespace) {
+        values.pop();
+    }
+    let start = values
+        .iter()
+        .position(|v| !is_whitespace(v))
+        .unwrap_or(values.len());
+    values.split_off(start)
+}
+
+fn is_whitespace(v: &ComponentValue) -> bool {
+    matches!(v, ComponentValue::PreservedToken(token_and_span) if matches!(token_and_span.token, Token::WhiteSpace { .. }))
+}
+
+fn group_span(values: &[ComponentValue]) -> Span {
+    match (values.first(), values.last()) {
+        (Some(first), Some(last)) => first.span().with_hi(last.span().hi()),
+        _ => Span::default(),
+    }
+}
+
+/// Returns a name of a `$name` parameter reference.
+fn param_ref<'a>(v: &ComponentValue, next: Option<&'a ComponentValue>) -> Option<&'a JsWord> {
+    match v {
+        ComponentValue::PreservedToken(token_and_span) => match token_and_span.token {
+            Token::Delim { value: '$' } => {}
+            _ => return None,
+        },
+        _ => return None,
+    }
+    match next? {
+        ComponentValue::Ident(ident) => Some(&ident.value),
+        ComponentValue::PreservedToken(token_and_span) => match &token_and_span.token {
+            Token::Ident { value, .. } => Some(value),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Parses `$name`, `$name <type>` and `$name <type>: default` parameters.
+fn parse_param(arg: &[ComponentValue]) -> Result<MixinParam, (Span, String)> {
+    let span = group_span(arg);
+    let invalid = || {
+        (
+            span,
+            "Invalid mixin parameter, expected '$name', '$name <type>' or '$name: default'"
+                .to_string(),
+        )
+    };
+    let name = param_ref(arg.first().ok_or_else(invalid)?, arg.get(1))
+        .ok_or_else(invalid)?
+        .clone();
+    let mut rest = arg[2..].iter().filter(|v| !is_whitespace(v)).peekable();
+    let mut ty = None;
+    if is_delim(rest.peek().copied(), '<') {
+        rest.next();
+        let ty_name = match rest.next() {
+            Some(ComponentValue::PreservedToken(token_and_span)) => match &token_and_span.token {
+                Token::Ident { value, .. } => value.clone(),
+                _ => return Err(invalid()),
+            },
+            _ => return Err(invalid()),
+        };
+        if !is_delim(rest.next(), '>') {
+            return Err(invalid());
+        }
+        ty = Some(MixinParamType::from_name(&ty_name).ok_or_else(|| {
+            (
+                span,
+                format!(
+                    "Unknown mixin parameter type '<{}>', supported types: <length>, <number>, \
+                     <percentage>, <time>, <angle>, <color>, <ident>, <string>",
+                    ty_name
+                ),
+            )
+        })?);
+    }
+    let default = match rest.next() {
+        None => None,
+        Some(ComponentValue::PreservedToken(token_and_span))
+            if matches!(token_and_span.token, Token::Colon) =>
+        {
+            let value: Vec<ComponentValue> = rest.cloned().collect();
+            let value = serialize_component_values(&value);
+            if value.trim().is_empty() {
+                return Err(invalid());
+            }
+            if let Some(ty) = ty {
+                if !ty.matches(&value) {
+                    return Err((
+                        span,
+                        format!(
+                            "Default value '{}' of a mixin parameter '${}' should be a <{}>",
+                            value.trim(),
+                            name,
+                            ty.name()
+                        ),
+                    ));
+                }
+            }
+            Some(parse_declaration_value("a", &value, span).ok_or_else(invalid)?)
+        }
+        _ => return Err(invalid()),
+    };
+    Ok(MixinParam { name, ty, default })
+}
+
+fn is_delim(v: Option<&ComponentValue>, c: char) -> bool {
+    match v {
+        Some(ComponentValue::PreservedToken(token_and_span)) => {
+            matches!(token_and_span.token, Token::Delim { value } if value == c)
+        }
+        _ => false,
+    }
+}
+
+/// Extracts module-local mixins from `@mixin` rules and removes them from the
+/// stylesheet.
+pub fn extract_local_mixins(stylesheet: &mut Stylesheet, expand_mixins: &mut ExpandMixins) {
+    stylesheet.rules.retain(|rule| {
+        if let Rule::AtRule(at_rule) = rule {
+            if &at_rule.name == "mixin" {
+                match parse_mixin(at_rule) {
+                    Ok((name, mixin)) => {
+                        expand_mixins.local.insert(name, Rc::new(mixin));
+                    }
+                    Err((span, message)) => emit_error(span, &message),
+                }
+                return false;
+            } else if &at_rule.name == "include" {
+                emit_error(at_rule.span, "@include should be used inside of a rule");
+                return false;
+            }
+        }
+        true
+    });
+}
+
+fn emit_error(span: Span, message: &str) {
+    HANDLER.with(|handler| {
+        handler.struct_span_err(span, message).emit();
+    });
+}
+
+/// [ExpandMixins] replaces `@include` rules with mixin declarations.
+pub struct ExpandMixins<'a> {
+    global: &'a FxHashMap<JsWord, Rc<Mixin>>,
+    /// Module-local mixins, local mixins are shadowing global mixins.
+    pub local: FxHashMap<JsWord, Rc<Mixin>>,
+    /// Names of global mixins that were used in the module.
+    pub used: FxHashSet<JsWord>,
+    /// Names of mixins that are currently expanded.
+    stack: Vec<JsWord>,
+}
+
+impl<'a> ExpandMixins<'a> {
+    pub fn new(global: &'a FxHashMap<JsWord, Rc<Mixin>>) -> Self {
+        Self {
+            global,
+            local: FxHashMap::default(),
+            used: FxHashSet::default(),
+            stack: Vec::new(),
+        }
+    }
+
+    fn expand_values(&mut self, values: &mut Vec<ComponentValue>) {
+        let has_includes = values
+            .iter()
+            .any(|v| matches!(v, ComponentValue::AtRule(at_rule) if &at_rule.name == "include"));
+        if has_includes {
+            let mut result = Vec::with_capacity(values.len());
+            for v in values.drain(..) {
+                match v {
+                    ComponentValue::AtRule(at_rule) if &at_rule.name == "include" => {
+                        result.extend(self.expand(&at_rule));
+                    }
+                    v => result.push(v),
+                }
+            }
+            *values = result;
+        }
+        for v in values.iter_mut() {
+            v.visit_mut_with(self);
+        }
+    }
+
+    /// Expands an `@include` rule.
+    fn expand(&mut self, at_rule: &AtRule) -> Vec<ComponentValue> {
+        let (name, args) = match parse_call(at_rule) {
+            Ok(call) => call,
+            Err((span, message)) => {
+                emit_error(span, &message);
+                return Vec::new();
+            }
+        };
+        let mixin = if let Some(mixin) = self.local.get(&name) {
+            mixin.clone()
+        } else if let Some(mixin) = self.global.get(&name) {
+            self.used.insert(name.clone());
+            mixin.clone()
+        } else {
+            emit_error(at_rule.span, &format!("Cannot find a mixin '{}'", name));
+            return Vec::new();
+        };
+        if self.stack.contains(&name) {
+            emit_error(
+                at_rule.span,
+                &format!("Mixin '{}' is included recursively", name),
+            );
+            return Vec::new();
+        }
+
+        let required = mixin
+            .params
+            .iter()
+            .filter(|param| param.default.is_none())
+            .count();
+        if args.len() < required || args.len() > mixin.params.len() {
+            let expected = if required == mixin.params.len() {
+                required.to_string()
+            } else {
+                format!("{} to {}", required, mixin.params.len())
+            };
+            emit_error(
+                at_rule.span,
+                &format!(
+                    "Mixin '{}' expects {} arguments but got {}",
+                    name,
+                    expected,
+                    args.len()
+                ),
+            );
+            return Vec::new();
+        }
+
+        let mut values = FxHashMap::default();
+        let mut has_errors = false;
+        for (index, param) in mixin.params.iter().enumerate() {
+            let value = if let Some(arg) = args.get(index) {
+                let span = group_span(arg);
+                let arg = serialize_component_values(arg);
+                if let Some(ty) = param.ty {
+                    if !ty.matches(&arg) {
+                        emit_error(
+                            span,
+                            &format!(
+                                "Argument '${}' of a mixin '{}' should be a <{}>, got '{}'",
+                                param.name,
+                                name,
+                                ty.name(),
+                                arg.trim()
+                            ),
+                        );
+                        has_errors = true;
+                        continue;
+                    }
+                }
+                // arguments are parsed as declaration values, so that vars
+                // and const values are resolved by the following passes
+                if let Some(value) = parse_declaration_value("a", &arg, span) {
+                    value
+                } else {
+                    emit_error(span, "Invalid mixin argument");
+                    has_errors = true;
+                    continue;
+                }
+            } else {
+                let mut value = param.default.clone().unwrap_or_default();
+                for v in value.iter_mut() {
+                    v.visit_mut_with(&mut ReplaceSpan(at_rule.span));
+                }
+                value
+            };
+            values.insert(param.name.clone(), value);
+        }
+        if has_errors {
+            return Vec::new();
+        }
+
+        let mut body = mixin.body.clone();
+        let mut substitute = SubstituteParams {
+            mixin: &name,
+            values: &values,
+            has_errors: false,
+        };
+        for v in body.iter_mut() {
+            v.visit_mut_with(&mut ReplaceSpan(at_rule.span));
+        }
+        substitute.substitute(&mut body);
+        for v in body.iter_mut() {
+            v.visit_mut_with(&mut substitute);
+        }
+        if substitute.has_errors {
+            return Vec::new();
+        }
+        let mut body =
+            match parse_declaration_list(&serialize_component_values(&body), at_rule.span) {
+                Some(body) => body,
+                None => {
+                    emit_error(
+                        at_rule.span,
+                        &format!(
+                            "Invalid mixin block '{}', mixins should contain declarations and \
+                             nested rules",
+                            name
+                        ),
+                    );
+                    return Vec::new();
+                }
+            };
+        self.stack.push(name);
+        self.expand_values(&mut body);
+        self.stack.pop();
+        body
+    }
+}
+
+impl VisitMut for ExpandMixins<'_> {
+    fn visit_mut_simple_block(&mut self, block: &mut SimpleBlock) {
+        self.expand_values(&mut block.value);
+    }
+}
+
+/// [SubstituteParams] replaces `$name` references in mixin declarations with
+/// argument values.
+struct SubstituteParams<'a> {
+    mixin: &'a JsWord,
+    values: &'a FxHashMap<JsWord, Vec<ComponentValue>>,
+    has_errors: bool,
+}
+
+impl SubstituteParams<'_> {
+    fn substitute(&mut self, values: &mut Vec<ComponentValue>) {
+        if !values.iter().any(|v| is_delim(Some(v), '$')) {
+            return;
+        }
+        let mut result = Vec::with_capacity(values.len());
+        let mut iter = take(values).into_iter().peekable();
+        while let Some(v) = iter.next() {
+            if let Some(name) = param_ref(&v, iter.peek()).cloned() {
+                if let Some(value) = self.values.get(&name) {
+                    result.extend(value.iter().cloned());
+                    iter.next();
+                    continue;
+                }
+                emit_error(
+                    v.span(),
+                    &format!(
+                        "Cannot find a parameter '${}' in a mixin '{}'",
+                        name, self.mixin
+                    ),
+                );
+                self.has_errors = true;
+            }
+            result.push(v);
+        }
+        *values = result;
+    }
+}
+
+impl VisitMut for SubstituteParams<'_> {
+    fn visit_mut_simple_block(&mut self, block: &mut SimpleBlock) {
+        self.substitute(&mut block.value);
+        block.visit_mut_children_with(self);
+    }
+
+    fn visit_mut_function(&mut self, func: &mut Function) {
+        self.substitute(&mut func.value);
+        func.visit_mut_children_with(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+    use crate::{
+        compiler::tests::{compile_css, compile_module},
+        config::Config,
+        const_map::{extract_const_values, ResolvedConsts},
+    };
+
+    #[test]
+    fn param_types() {
+        assert!(MixinParamType::Length.matches("2px"));
+        assert!(MixinParamType::Length.matches("0"));
+        assert!(!MixinParamType::Length.matches("red"));
+        assert!(MixinParamType::Length.matches("var(--a)"));
+        assert!(MixinParamType::Color.matches("#fff"));
+        assert!(MixinParamType::Color.matches("rgba(0, 0, 0, 0.5)"));
+        assert!(MixinParamType::Color.matches("red"));
+        assert!(MixinParamType::Color.matches("RebeccaPurple"));
+        assert!(MixinParamType::Color.matches("currentcolor"));
+        assert!(MixinParamType::Color.matches("transparent"));
+        assert!(!MixinParamType::Color.matches("solid"));
+        assert!(!MixinParamType::Color.matches("2px"));
+        assert!(MixinParamType::Time.matches("200ms"));
+        assert!(MixinParamType::Percentage.matches("50%"));
+        assert!(!MixinParamType::Number.matches("1px"));
+        assert!(MixinParamType::String.matches("'...'"));
+        assert!(!MixinParamType::Ident.matches("1a"));
+        assert!(MixinParamType::Length.matches("100dvh"));
+        assert!(MixinParamType::Length.matches("50svh"));
+        assert!(MixinParamType::Length.matches("1lh"));
+        assert!(MixinParamType::Length.matches("10cqw"));
+        assert!(MixinParamType::Length.matches("2vi"));
+        assert!(MixinParamType::Length.matches("min(1px, 2vb)"));
+        assert!(MixinParamType::Length.matches("max(1px, 2em)"));
+        assert!(MixinParamType::Length.matches("clamp(1rem, 2vw, 3rem)"));
+        assert!(!MixinParamType::Length.matches("1dvx"));
+    }
+
+    fn css(source: &str) -> String {
+        compile_css(source).unwrap().css
+    }
+
+    fn error(source: &str) -> String {
+        compile_css(source).unwrap_err().message
+    }
+
+    #[test]
+    fn parse() {
+        let file = extract_const_values(
+            Path::new("const.css"),
+            "@mixin ring($color <color>, $width <length>: 2px) { outline: $width solid $color; }"
+                .to_string(),
+        )
+        .unwrap();
+        let mixin = &file.mixins[&JsWord::from("ring")];
+        assert_eq!(&mixin.params[0].name, "color");
+        assert_eq!(mixin.params[0].ty, Some(MixinParamType::Color));
+        assert_eq!(mixin.params[1].ty, Some(MixinParamType::Length));
+        assert_eq!(
+            serialize_component_values(mixin.params[1].default.as_ref().unwrap()),
+            "2px"
+        );
+        assert!(serialize_component_values(&mixin.body).contains("$width solid $color"));
+
+        let invalid = |source: &str| {
+            extract_const_values(Path::new("const.css"), source.to_string()).unwrap_err()
+        };
+        assert!(invalid("@mixin a($b <size>) {}").contains("Unknown mixin parameter type"));
+        assert!(invalid("@mixin a($b, $b) {}").contains("Duplicate mixin parameter '$b'"));
+        assert!(invalid("@mixin a($b <length>: red) {}").contains("should be a <length>"));
+        assert!(invalid("@mixin a($b) ;").contains("Expected mixin block"));
+    }
+
+    #[test]
+    fn expand() {
+        assert_eq!(
+            css(
+                "@mixin ring($color <color>, $width <length>) { outline: $width solid $color; \
+                 &:focus { outline-offset: $width; } }\n.a { @include ring(red, 2px); }"
+            ),
+            ".a {\n  outline: 2px solid red;\n  &:focus {\n    outline-offset: 2px;\n  }\n}"
+        );
+        assert_eq!(
+            css("@mixin reset { margin: 0; padding: 0; }\n.a { color: red; @include reset; }"),
+            ".a {\n  color: red;\n  margin: 0;\n  padding: 0;\n}"
+        );
+    }
+
+    #[test]
+    fn defaults() {
+        let source =
+            "@mixin ring($color, $width <length>: 2px) { outline: $width solid $color; }\n";
+        assert_eq!(
+            css(&format!("{}.a {{ @include ring(red); }}", source)),
+            ".a {\n  outline: 2px solid red;\n}"
+        );
+        assert_eq!(
+            css(&format!("{}.a {{ @include ring(red, 1px); }}", source)),
+            ".a {\n  outline: 1px solid red;\n}"
+        );
+    }
+
+    #[test]
+    fn arity_errors() {
+        let source = "@mixin ring($color, $width: 2px) { outline: $width solid $color; }\n";
+        assert!(error(&format!("{}.a {{ @include ring; }}", source))
+            .contains("Mixin 'ring' expects 1 to 2 arguments but got 0"));
+        assert!(
+            error(&format!("{}.a {{ @include ring(red, 1px, 2px); }}", source))
+                .contains("Mixin 'ring' expects 1 to 2 arguments but got 3")
+        );
+        assert!(error("@mixin a { color: red; }\n.a { @include a(red); }")
+            .contains("Mixin 'a' expects 0 arguments but got 1"));
+    }
+
+    #[test]
+    fn type_errors() {
+        let message =
+            error("@mixin a($width <length>) { width: $width; }\n.a { @include a(red); }");
+        assert!(
+            message.contains("Argument '$width' of a mixin 'a' should be a <length>, got 'red'")
+        );
+        assert!(error("@mixin a { color: $color; }\n.a { @include a; }")
+            .contains("Cannot find a parameter '$color' in a mixin 'a'"));
+        assert!(error(".a { @include b; }").contains("Cannot find a mixin 'b'"));
+    }
+
+    #[test]
+    fn recursion() {
+        assert!(
+            error("@mixin a { @include b; }\n@mixin b { @include a; }\n.a { @include a; }")
+                .contains("Mixin 'a' is included recursively")
+        );
+        assert_eq!(
+            css(
+                "@mixin a { color: red; }\n@mixin b { @include a; @include a; }\n.a { @include b; \
+                 }"
+            ),
+            ".a {\n  color: red;\n  color: red;\n}"
+        );
+    }
+
+    #[test]
+    fn local_shadows_global() {
+        let file = extract_const_values(
+            Path::new("const.css"),
+            "@mixin a { color: red; }\n@mixin b { color: blue; }".to_string(),
+        )
+        .unwrap();
+        let consts = ResolvedConsts {
+            mixins: file.mixins,
+            ..Default::default()
+        };
+        let artifact = compile_module(
+            "@mixin a { color: green; }\n.a { @include a; @include b; }",
+            &consts,
+            &Config::default().compiler_options(),
+        )
+        .unwrap();
+        assert_eq!(artifact.css, ".a {\n  color: green;\n  color: blue;\n}");
+        assert_eq!(artifact.mixins, [JsWord::from("b")]);
+    }
+}
@@ -0,0 +1,98 @@
+use std::fmt;
+
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::compiler::is_valid_js_ident;
+
+/// Naming transform for TS enum members.
+///
+/// Local IDs are CSS identifiers and can contain chars that aren't allowed in
+/// JS identifiers, e.g. `button-primary`. Words are separated by these chars.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Hash,
+    clap::ValueEnum,
+    Serialize,
+    Deserialize,
+    JsonSchema,
+)]
+pub enum TsNaming {
+    /// `button-primary` => `buttonPrimary`, the first char isn't changed.
+    #[default]
+    #[serde(rename = "camelCase")]
+    #[value(name = "camelCase")]
+    CamelCase,
+    /// `button-primary` => `ButtonPrimary`.
+    #[serde(rename = "PascalCase")]
+    #[value(name = "PascalCase")]
+    PascalCase,
+}
+
+impl fmt::Display for TsNaming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.to_possible_value().unwrap().get_name().fmt(f)
+    }
+}
+
+impl TsNaming {
+    /// Converts a local ID into a JS identifier.
+    ///
+    /// Valid JS identifiers are preserved in camelCase, names that start with
+    /// a digit after the transform are prefixed with `_`.
+    pub fn member_name(self, local_id: &str) -> String {
+        let mut result = String::with_capacity(local_id.len());
+        let mut is_word_start = self == TsNaming::PascalCase;
+        for c in local_id.chars() {
+            if c.is_ascii_alphanumeric() || c == '_' {
+                if is_word_start {
+                    result.push(c.to_ascii_uppercase());
+                    is_word_start = false;
+                } else {
+                    result.push(c);
+                }
+            } else if !result.is_empty() || self == TsNaming::PascalCase {
+                is_word_start = true;
+            }
+        }
+        if !is_valid_js_ident(&result) {
+            result.insert(0, '_');
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn camel_case() {
+        let n = TsNaming::CamelCase;
+        assert_eq!(n.member_name("button"), "button");
+        assert_eq!(n.member_name("buttonPrimary"), "buttonPrimary");
+        assert_eq!(n.member_name("Button"), "Button");
+        assert_eq!(n.member_name("button_primary"), "button_primary");
+        assert_eq!(n.member_name("button-primary"), "buttonPrimary");
+        assert_eq!(n.member_name("-webkit-box"), "webkitBox");
+        assert_eq!(n.member_name("sm:p-2.5"), "smP25");
+        assert_eq!(n.member_name("--1x"), "_1x");
+        assert_eq!(n.member_name("--"), "_");
+    }
+
+    #[test]
+    fn pascal_case() {
+        let n = TsNaming::PascalCase;
+        assert_eq!(n.member_name("button"), "Button");
+        assert_eq!(n.member_name("buttonPrimary"), "ButtonPrimary");
+        assert_eq!(n.member_name("button-primary"), "ButtonPrimary");
+        assert_eq!(n.member_name("button_primary"), "Button_primary");
+        assert_eq!(n.member_name("-webkit-box"), "WebkitBox");
+        assert_eq!(n.member_name("a:hover"), "AHover");
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    env, fs, io,
+    path::{Component, Path, PathBuf},
+    process,
+};
+
+/// Writes a file atomically.
+///
+/// Contents are written into a temporary file in the same directory that is
+/// renamed to the destination path, so other processes never observe
+/// partially written files.
+pub fn write_atomic<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> io::Result<()> {
+    let path = path.as_ref();
+    let tmp_path = tmp_path(path);
+    if let Err(err) = fs::write(&tmp_path, contents).and_then(|_| fs::rename(&tmp_path, path)) {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// Returns a path to a temporary dot file, e.g. `build/.a.css.1234.tmp`.
+fn tmp_path(path: &Path) -> PathBuf {
+    let mut file_name = std::ffi::OsString::from(".");
+    if let Some(name) = path.file_name() {
+        file_name.push(name);
+    }
+    file_name.push(format!(".{}.tmp", process::id()));
+    path.with_file_name(file_name)
+}
+
+/// Returns a relative URL from a directory to a path, e.g. it is used for
+/// source paths in source maps.
+///
+/// Relative paths are resolved from the current directory.
+pub fn relative_url(from_dir: &Path, to: &Path) -> String {
+    let cwd = env::current_dir().unwrap_or_default();
+    let from_dir = normalize(&cwd.join(from_dir));
+    let to = normalize(&cwd.join(to));
+    let common = from_dir
+        .iter()
+        .zip(to.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let mut parts: Vec<String> = vec!["..".to_string(); from_dir.len() - common];
+    parts.extend(to[common..].iter().cloned());
+    parts.join("/")
+}
+
+/// Returns path components without `.` and `..` components.
+fn normalize(path: &Path) -> Vec<String> {
+    let mut result: Vec<String> = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                result.pop();
+            }
+            Component::Normal(name) => result.push(name.to_string_lossy().into_owned()),
+            Component::Prefix(prefix) => {
+                result.push(prefix.as_os_str().to_string_lossy().into_owned())
+            }
+            Component::RootDir => {}
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tmp_file_is_a_sibling_dot_file() {
+        let path = tmp_path(Path::new("build/css/a.css"));
+        assert_eq!(path.parent(), Some(Path::new("build/css")));
+        let name = path.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with(".a.css."));
+        assert!(name.ends_with(".tmp"));
+    }
+
+    #[test]
+    fn relative_urls() {
+        assert_eq!(
+            relative_url(Path::new("build/css/ui"), Path::new("css/ui/a.xiss")),
+            "../../../css/ui/a.xiss"
+        );
+        assert_eq!(
+            relative_url(Path::new("./build"), Path::new("build/a.xiss")),
+            "a.xiss"
+        );
+        assert_eq!(
+            relative_url(Path::new("/a/b/c"), Path::new("/a/d/e.xiss")),
+            "../../d/e.xiss"
+        );
+    }
+
+    #[test]
+    fn write_replaces_file() {
+        let dir = std::env::temp_dir().join(format!("xiss-output-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.css");
+        write_atomic(&path, "a").unwrap();
+        write_atomic(&path, "b").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "b");
+        assert!(!tmp_path(&path).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,425 @@
+use std::borrow::Cow;
+
+use regex::{Captures, Regex};
+use rustc_hash::FxHashMap;
+
+/// At-rules with preludes that can use `const()` values.
+const CONST_PRELUDE_AT_RULES: [&str; 3] = ["media", "supports", "container"];
+
+/// [PreludeSubstitution] replaces `const()` values and custom media queries
+/// in at-rule preludes before a module is parsed.
+///
+/// At-rule preludes are parsed by the CSS parser into media queries and
+/// supports conditions that can't contain arbitrary functions, so values
+/// are substituted in the module source. Module-local values declared in
+/// `:const {}` blocks are shadowing global values.
+pub struct PreludeSubstitution<C, M> {
+    const_re: Regex,
+    custom_media_re: Regex,
+    lookup_const: C,
+    lookup_custom_media: M,
+    /// Module-local const values with substituted const references.
+    local_consts: FxHashMap<String, String>,
+    /// Names of global const values that were substituted.
+    pub used_consts: Vec<String>,
+    /// Names of custom media queries that were substituted.
+    pub used_custom_media: Vec<String>,
+    pub errors: Vec<SourceError>,
+}
+
+impl<C, M> PreludeSubstitution<C, M>
+where
+    C: Fn(&str) -> Option<String>,
+    M: Fn(&str) -> Option<String>,
+{
+    pub fn new(lookup_const: C, lookup_custom_media: M) -> Self {
+        Self {
+            const_re: Regex::new(r"(^|[^A-Za-z0-9_-])const\(\s*--([A-Za-z0-9_-]+)\s*\)").unwrap(),
+            custom_media_re: Regex::new(r"(^|[^A-Za-z0-9_-])\(\s*(--[A-Za-z0-9_-]+)\s*\)").unwrap(),
+            lookup_const,
+            lookup_custom_media,
+            local_consts: FxHashMap::default(),
+            used_consts: Vec::new(),
+            used_custom_media: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Substitutes values in at-rule preludes, returns `None` when source
+    /// doesn't have any substitutions.
+    pub fn substitute(&mut self, source: &str) -> Option<String> {
+        self.local_consts.clear();
+        for (name, value) in find_local_consts(source) {
+            // local const values can use global and previously declared local
+            // const values, missing values are reported by the compiler
+            let value = self.substitute_consts(value, None).into_owned();
+            self.local_consts.insert(name.to_string(), value);
+        }
+        let mut result = String::new();
+        let mut last = 0;
+        for (name, start, end) in find_at_rule_preludes(source) {
+            if !CONST_PRELUDE_AT_RULES
+                .iter()
+                .any(|n| n.eq_ignore_ascii_case(name))
+            {
+                continue;
+            }
+            let prelude = &source[start..end];
+            let mut substituted = self.substitute_consts(prelude, Some(start)).into_owned();
+            if name.eq_ignore_ascii_case("media") {
+                self.check_custom_media(prelude, start);
+                substituted = self.substitute_custom_media(&substituted).into_owned();
+            }
+            if substituted != prelude {
+                result.push_str(&source[last..start]);
+                result.push_str(&substituted);
+                last = end;
+            }
+        }
+        if last == 0 {
+            None
+        } else {
+            result.push_str(&source[last..]);
+            Some(result)
+        }
+    }
+
+    /// Substitutes const values, missing values are reported when `offset`
+    /// of the prelude in the module source is specified.
+    fn substitute_consts<'a>(&mut self, prelude: &'a str, offset: Option<usize>) -> Cow<'a, str> {
+        let re = self.const_re.clone();
+        re.replace_all(prelude, |caps: &Captures| {
+            let name = &caps[2];
+            if let Some(value) = self.local_consts.get(name) {
+                format!("{}{}", &caps[1], value)
+            } else if let Some(value) = (self.lookup_const)(name) {
+                self.used_consts.push(name.to_string());
+                format!("{}{}", &caps[1], value)
+            } else {
+                if let Some(offset) = offset {
+                    self.errors.push(SourceError::new(
+                        offset + caps.get(0).unwrap().start() + caps[1].len(),
+                        format!("Cannot find a const value '--{}'", name),
+                    ));
+                }
+                caps[0].to_string()
+            }
+        })
+    }
+
+    /// Reports missing custom media queries in a prelude before const values
+    /// are substituted, so that error offsets point to the module source.
+    fn check_custom_media(&mut self, prelude: &str, offset: usize) {
+        for caps in self.custom_media_re.captures_iter(prelude) {
+            let name = &caps[2];
+            if (self.lookup_custom_media)(name).is_none() {
+                self.errors.push(SourceError::new(
+                    offset + caps.get(0).unwrap().start() + caps[1].len(),
+                    format!("Cannot find a custom media query '{}'", name),
+                ));
+            }
+        }
+    }
+
+    fn substitute_custom_media<'a>(&mut self, prelude: &'a str) -> Cow<'a, str> {
+        let re = self.custom_media_re.clone();
+        re.replace_all(prelude, |caps: &Captures| {
+            let name = &caps[2];
+            if let Some(query) = (self.lookup_custom_media)(name) {
+                self.used_custom_media.push(name.to_string());
+                format!("{}{}", &caps[1], query)
+            } else {
+                caps[0].to_string()
+            }
+        })
+    }
+}
+
+/// [SourceError] is an error reported by a pass that transforms module
+/// source before it is parsed.
+#[derive(Debug, PartialEq)]
+pub struct SourceError {
+    /// Byte offset in the source that was passed to the pass.
+    pub offset: usize,
+    pub message: String,
+}
+
+impl SourceError {
+    pub fn new(offset: usize, message: String) -> Self {
+        Self { offset, message }
+    }
+}
+
+/// Returns names and byte ranges of at-rule preludes, comments and strings
+/// are skipped.
+fn find_at_rule_preludes(source: &str) -> Vec<(&str, usize, usize)> {
+    let bytes = source.as_bytes();
+    let mut result = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = skip_comment(source, i);
+            }
+            b'"' | b'\'' => {
+                i = skip_string(bytes, i);
+            }
+            b'@' => {
+                let name_start = i + 1;
+                let mut name_end = name_start;
+                while name_end < bytes.len()
+                    && (bytes[name_end].is_ascii_alphanumeric()
+                        || bytes[name_end] == b'-'
+                        || bytes[name_end] == b'_')
+                {
+                    name_end += 1;
+                }
+                let mut end = name_end;
+                let mut depth = 0;
+                while end < bytes.len() {
+                    match bytes[end] {
+                        b'/' if bytes.get(end + 1) == Some(&b'*') => {
+                            end = skip_comment(source, end);
+                            continue;
+                        }
+                        b'"' | b'\'' => {
+                            end = skip_string(bytes, end);
+                            continue;
+                        }
+                        b'(' => depth += 1,
+                        b')' => depth -= 1,
+                        b'{' | b';' if depth <= 0 => break,
+                        _ => {}
+                    }
+                    end += 1;
+                }
+                result.push((&source[name_start..name_end], name_end, end));
+                i = name_end;
+            }
+            _ => i += 1,
+        }
+    }
+    result
+}
+
+/// Returns names without `--` prefix and values of const declarations from
+/// top-level `:const {}` blocks.
+fn find_local_consts(source: &str) -> Vec<(&str, &str)> {
+    let bytes = source.as_bytes();
+    let mut result = Vec::new();
+    let mut prelude_start = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = skip_comment(source, i);
+                if source[prelude_start..i].trim_start().starts_with("/*") {
+                    prelude_start = i;
+                }
+                continue;
+            }
+            b'"' | b'\'' => {
+                i = skip_string(bytes, i);
+                continue;
+            }
+            b';' => prelude_start = i + 1,
+            b'{' => {
+                let end = find_block_end(source, i).unwrap_or(source.len());
+                if source[prelude_start..i].trim() == ":const" {
+                    for decl in split_declarations(&source[i + 1..end]) {
+                        if let Some((name, value)) = decl.split_once(':') {
+                            if let Some(name) = name.trim().strip_prefix("--") {
+                                result.push((name, value.trim()));
+                            }
+                        }
+                    }
+                }
+                i = end + 1;
+                prelude_start = i;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    result
+}
+
+/// Splits a declaration list with `;` separators, comments are skipped.
+fn split_declarations(source: &str) -> Vec<&str> {
+    let bytes = source.as_bytes();
+    let mut result = Vec::new();
+    let mut start = 0;
+    let mut depth = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let end = skip_comment(source, i);
+                if source[start..i].trim().is_empty() {
+                    start = end;
+                }
+                i = end;
+                continue;
+            }
+            b'"' | b'\'' => {
+                i = skip_string(bytes, i);
+                continue;
+            }
+            b'(' | b'[' | b'{' => depth += 1,
+            b')' | b']' | b'}' => depth -= 1,
+            b';' if depth <= 0 => {
+                result.push(&source[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    if !source[start..].trim().is_empty() {
+        result.push(&source[start..]);
+    }
+    result
+}
+
+/// Returns an index of a closing brace of a block that starts at `start`.
+pub(crate) fn find_block_end(source: &str, start: usize) -> Option<usize> {
+    let bytes = source.as_bytes();
+    let mut depth = 0;
+    let mut i = start;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                i = skip_comment(source, i);
+                continue;
+            }
+            b'"' | b'\'' => {
+                i = skip_string(bytes, i);
+                continue;
+            }
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i);
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// Returns an index after the end of a comment.
+pub(crate) fn skip_comment(source: &str, start: usize) -> usize {
+    if let Some(end) = source[start + 2..].find("*/") {
+        start + 2 + end + 2
+    } else {
+        source.len()
+    }
+}
+
+/// Returns an index after the end of a string.
+pub(crate) fn skip_string(bytes: &[u8], start: usize) -> usize {
+    let quote = bytes[start];
+    let mut i = start + 1;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\\' => i += 1,
+            c if c == quote || c == b'\n' => return i + 1,
+            _ => {}
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn substitute(source: &str) -> (Option<String>, Vec<SourceError>) {
+        let mut s = PreludeSubstitution::new(
+            |name| match name {
+                "TABLET" => Some("768px".to_string()),
+                _ => None,
+            },
+            |name| match name {
+                "--tablet" => Some("(min-width: 768px)".to_string()),
+                _ => None,
+            },
+        );
+        let result = s.substitute(source);
+        (result, s.errors)
+    }
+
+    #[test]
+    fn media_const() {
+        assert_eq!(
+            substitute("@media (min-width: const(--TABLET)) { .a { color: red; } }").0,
+            Some("@media (min-width: 768px) { .a { color: red; } }".to_string())
+        );
+    }
+
+    #[test]
+    fn supports_and_container_const() {
+        assert_eq!(
+            substitute("@supports (width: const(--TABLET)) {}").0,
+            Some("@supports (width: 768px) {}".to_string())
+        );
+        assert_eq!(
+            substitute("@container a (min-width: const( --TABLET )) {}").0,
+            Some("@container a (min-width: 768px) {}".to_string())
+        );
+    }
+
+    #[test]
+    fn custom_media() {
+        assert_eq!(
+            substitute("@media screen and (--tablet) {}").0,
+            Some("@media screen and (min-width: 768px) {}".to_string())
+        );
+    }
+
+    #[test]
+    fn declarations_are_ignored() {
+        assert_eq!(substitute(".a { width: const(--TABLET); }").0, None);
+        assert_eq!(substitute("@import 'const(--TABLET)';").0, None);
+        assert_eq!(
+            substitute("/* @media (min-width: const(--TABLET)) */").0,
+            None
+        );
+    }
+
+    #[test]
+    fn missing_values() {
+        let (result, errors) = substitute("@media (min-width: const(--A)) and (--b) {}");
+        assert_eq!(result, None);
+        assert_eq!(
+            errors,
+            [
+                SourceError::new(19, "Cannot find a const value '--A'".to_string()),
+                SourceError::new(35, "Cannot find a custom media query '--b'".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn local_consts() {
+        assert_eq!(
+            substitute(
+                "/* Breakpoints */\n:const {\n  --TABLET: 1024px;\n  /* Wide */ --WIDE: \
+                 calc(const(--TABLET) * 2);\n}\n@media (min-width: const(--WIDE)) {}"
+            )
+            .0
+            .unwrap(),
+            "/* Breakpoints */\n:const {\n  --TABLET: 1024px;\n  /* Wide */ --WIDE: \
+             calc(const(--TABLET) * 2);\n}\n@media (min-width: calc(1024px * 2)) {}"
+        );
+        assert_eq!(
+            find_local_consts(".a { --A: 1px; }\n:const { --B: 2px; --C: url(\"a;b\") }"),
+            [("B", "2px"), ("C", "url(\"a;b\")")]
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use std::fmt::{self, Write};
+
+use serde_json::json;
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Mapping from a generated position to a source position, lines and columns
+/// are zero-based.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mapping {
+    pub generated_line: u32,
+    pub generated_column: u32,
+    pub source_line: u32,
+    pub source_column: u32,
+}
+
+/// Returns a source map v3 JSON for a generated file with a single source.
+pub fn source_map_json(file: &str, source: &str, mappings: &[Mapping]) -> String {
+    json!({
+        "version": 3,
+        "file": file,
+        "sources": [source],
+        "names": [],
+        "mappings": encode_mappings(mappings),
+    })
+    .to_string()
+}
+
+/// Encodes mappings into a `mappings` string, mappings should be sorted by
+/// generated positions.
+pub fn encode_mappings(mappings: &[Mapping]) -> String {
+    let mut result = String::new();
+    let mut line = 0;
+    let mut prev_generated_column = 0;
+    let mut prev_source_line = 0;
+    let mut prev_source_column = 0;
+    let mut is_first_segment = true;
+    for m in mappings {
+        while line < m.generated_line {
+            result.push(';');
+            line += 1;
+            prev_generated_column = 0;
+            is_first_segment = true;
+        }
+        if !is_first_segment {
+            result.push(',');
+        }
+        is_first_segment = false;
+        encode_vlq(
+            &mut result,
+            m.generated_column as i64 - prev_generated_column,
+        );
+        // all mappings are using the first source
+        encode_vlq(&mut result, 0);
+        encode_vlq(&mut result, m.source_line as i64 - prev_source_line);
+        encode_vlq(&mut result, m.source_column as i64 - prev_source_column);
+        prev_generated_column = m.generated_column as i64;
+        prev_source_line = m.source_line as i64;
+        prev_source_column = m.source_column as i64;
+    }
+    result
+}
+
+/// Encodes a value in a Base64 VLQ format.
+pub fn encode_vlq(output: &mut String, value: i64) {
+    let mut v = if value < 0 {
+        ((-value) << 1) | 1
+    } else {
+        value << 1
+    };
+    loop {
+        let mut digit = v & 0b11111;
+        v >>= 5;
+        if v > 0 {
+            digit |= 0b100000;
+        }
+        output.push(BASE64_CHARS[digit as usize] as char);
+        if v == 0 {
+            break;
+        }
+    }
+}
+
+/// [LineIndex] converts byte offsets into lines and UTF-16 columns.
+pub struct LineIndex<'a> {
+    source: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(source: &'a str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(i, _)| i + 1));
+        Self {
+            source,
+            line_starts,
+        }
+    }
+
+    /// Returns zero-based line and column.
+    pub fn line_col(&self, offset: usize) -> (u32, u32) {
+        let offset = offset.min(self.source.len());
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+        let column: usize = self.source[self.line_starts[line]..offset]
+            .chars()
+            .map(char::len_utf16)
+            .sum();
+        (line as u32, column as u32)
+    }
+}
+
+/// [LineWriter] counts lines written into an output.
+pub struct LineWriter<W: Write> {
+    output: W,
+    /// Current zero-based line.
+    pub line: u32,
+}
+
+impl<W: Write> LineWriter<W> {
+    pub fn new(output: W) -> Self {
+        Self { output, line: 0 }
+    }
+}
+
+impl<W: Write> Write for LineWriter<W> {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.line += s.bytes().filter(|c| *c == b'\n').count() as u32;
+        self.output.write_str(s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vlq(value: i64) -> String {
+        let mut result = String::new();
+        encode_vlq(&mut result, value);
+        result
+    }
+
+    #[test]
+    fn vlq_values() {
+        assert_eq!(vlq(0), "A");
+        assert_eq!(vlq(1), "C");
+        assert_eq!(vlq(-1), "D");
+        assert_eq!(vlq(15), "e");
+        assert_eq!(vlq(16), "gB");
+        assert_eq!(vlq(-16), "hB");
+        assert_eq!(vlq(123456), "gkxH");
+    }
+
+    #[test]
+    fn mappings() {
+        let m = |generated_line, generated_column, source_line, source_column| Mapping {
+            generated_line,
+            generated_column,
+            source_line,
+            source_column,
+        };
+        assert_eq!(encode_mappings(&[]), "");
+        assert_eq!(
+            encode_mappings(&[m(0, 0, 0, 0), m(0, 4, 1, 2), m(2, 2, 0, 1)]),
+            "AAAA,IACE;;EADD"
+        );
+    }
+
+    #[test]
+    fn line_index() {
+        let index = LineIndex::new("a\nbc\n\u{1F600}d");
+        assert_eq!(index.line_col(0), (0, 0));
+        assert_eq!(index.line_col(1), (0, 1));
+        assert_eq!(index.line_col(2), (1, 0));
+        assert_eq!(index.line_col(3), (1, 1));
+        assert_eq!(index.line_col(5), (2, 0));
+        assert_eq!(index.line_col(9), (2, 2));
+    }
+
+    #[test]
+    fn line_writer() {
+        let mut output = String::new();
+        let mut writer = LineWriter::new(&mut output);
+        write!(writer, "a\nb").unwrap();
+        assert_eq!(writer.line, 1);
+        write!(writer, "\n\n").unwrap();
+        assert_eq!(writer.line, 3);
+        assert_eq!(output, "a\nb\n\n");
+    }
+
+    #[test]
+    fn json() {
+        let map = source_map_json(
+            "a.d.ts",
+            "../src/a.xiss",
+            &[Mapping {
+                generated_line: 1,
+                generated_column: 0,
+                source_line: 2,
+                source_column: 0,
+            }],
+        );
+        let value: serde_json::Value = serde_json::from_str(&map).unwrap();
+        assert_eq!(value["version"], 3);
+        assert_eq!(value["file"], "a.d.ts");
+        assert_eq!(value["sources"][0], "../src/a.xiss");
+        assert_eq!(value["mappings"], ";AAEA");
+    }
+}
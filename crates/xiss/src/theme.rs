@@ -0,0 +1,233 @@
+use crate::{
+    compiler::is_valid_js_ident,
+    preprocess::{skip_comment, skip_string, SourceError},
+};
+
+/// Attribute on the root element that selects a theme.
+pub const THEME_ATTRIBUTE: &str = "data-theme";
+
+/// [ThemeExpansion] expands `@theme` blocks before a module is parsed.
+///
+/// `@theme dark { --Surface: #111; }` is expanded into a
+/// `:root[data-theme="dark"]` rule, and themes with a media query, e.g.
+/// `@theme dark (prefers-color-scheme: dark) {}`, are also applied with a
+/// media rule when theme isn't selected explicitly. Media rules are appended
+/// to the end of the module, so that line numbers in diagnostics aren't
+/// changed.
+#[derive(Default)]
+pub struct ThemeExpansion {
+    /// Theme names in the declaration order.
+    pub names: Vec<String>,
+    pub errors: Vec<SourceError>,
+}
+
+impl ThemeExpansion {
+    /// Expands `@theme` blocks, returns `None` when source doesn't have any
+    /// theme blocks.
+    pub fn expand(&mut self, source: &str) -> Option<String> {
+        let bytes = source.as_bytes();
+        let mut result = String::new();
+        let mut media_rules = String::new();
+        let mut last = 0;
+        let mut depth = 0;
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                    i = skip_comment(source, i);
+                    continue;
+                }
+                b'"' | b'\'' => {
+                    i = skip_string(bytes, i);
+                    continue;
+                }
+                b'{' => depth += 1,
+                b'}' => depth -= 1,
+                b'@' if is_theme_keyword(&source[i..]) => {
+                    if depth > 0 {
+                        self.error(i, "@theme should be declared at the top level".to_string());
+                    } else if let Some((end, rule)) = self.expand_theme(source, i, &mut media_rules)
+                    {
+                        result.push_str(&source[last..i]);
+                        result.push_str(&rule);
+                        last = end;
+                        i = end;
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        if last == 0 {
+            return None;
+        }
+        result.push_str(&source[last..]);
+        result.push_str(&media_rules);
+        Some(result)
+    }
+
+    /// Expands a theme block that starts at `start`, returns an index after
+    /// the end of the block and a theme rule.
+    fn expand_theme(
+        &mut self,
+        source: &str,
+        start: usize,
+        media_rules: &mut String,
+    ) -> Option<(usize, String)> {
+        let bytes = source.as_bytes();
+        let prelude_start = start + "@theme".len();
+        let mut block_start = prelude_start;
+        while block_start < bytes.len() && !matches!(bytes[block_start], b'{' | b';' | b'}') {
+            block_start += 1;
+        }
+        if bytes.get(block_start) != Some(&b'{') {
+            self.error(
+                start,
+                "@theme should have a block with var declarations".to_string(),
+            );
+            return None;
+        }
+        let prelude = source[prelude_start..block_start].trim();
+        let (name, media) = match prelude.find(|c: char| c.is_whitespace() || c == '(') {
+            Some(index) => (&prelude[..index], prelude[index..].trim()),
+            None => (prelude, ""),
+        };
+        if !is_valid_js_ident(name) {
+            self.error(
+                start,
+                format!(
+                    "Invalid theme name '{}', theme name should be a valid JS identifier",
+                    name
+                ),
+            );
+            return None;
+        }
+
+        let mut end = block_start + 1;
+        while end < bytes.len() && bytes[end] != b'}' {
+            match bytes[end] {
+                b'/' if bytes.get(end + 1) == Some(&b'*') => {
+                    end = skip_comment(source, end);
+                    continue;
+                }
+                b'"' | b'\'' => {
+                    end = skip_string(bytes, end);
+                    continue;
+                }
+                b'{' => {
+                    self.error(
+                        end,
+                        format!("Theme '{}' should contain only var declarations", name),
+                    );
+                    return None;
+                }
+                _ => {}
+            }
+            end += 1;
+        }
+        if end >= bytes.len() {
+            self.error(block_start, format!("Theme '{}' block isn't closed", name));
+            return None;
+        }
+        let body = &source[block_start..end + 1];
+
+        if !self.names.iter().any(|n| n == name) {
+            self.names.push(name.to_string());
+        }
+        if !media.is_empty() {
+            media_rules.push_str(&format!(
+                "\n@media {} {{\n:root:not([{}]) {}\n}}\n",
+                media, THEME_ATTRIBUTE, body
+            ));
+        }
+        // line breaks from the prelude are preserved
+        let line_breaks = "\n".repeat(source[start..block_start].matches('\n').count());
+        Some((
+            end + 1,
+            format!(
+                ":root[{}=\"{}\"] {}{}",
+                THEME_ATTRIBUTE, name, body, line_breaks
+            ),
+        ))
+    }
+
+    fn error(&mut self, offset: usize, message: String) {
+        self.errors.push(SourceError::new(offset, message));
+    }
+}
+
+fn is_theme_keyword(s: &str) -> bool {
+    s.strip_prefix("@theme")
+        .is_some_and(|rest| rest.starts_with(|c: char| c.is_whitespace()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand(source: &str) -> (Option<String>, ThemeExpansion) {
+        let mut themes = ThemeExpansion::default();
+        let result = themes.expand(source);
+        (result, themes)
+    }
+
+    #[test]
+    fn theme_selector() {
+        let (result, themes) = expand(".a {}\n@theme dark { --Surface: #111; }\n.b {}");
+        assert_eq!(
+            result.unwrap(),
+            ".a {}\n:root[data-theme=\"dark\"] { --Surface: #111; }\n.b {}"
+        );
+        assert_eq!(themes.names, vec!["dark"]);
+    }
+
+    #[test]
+    fn theme_media() {
+        let (result, themes) =
+            expand("@theme dark (prefers-color-scheme: dark) { --Surface: #111; }\n.b {}");
+        assert_eq!(
+            result.unwrap(),
+            ":root[data-theme=\"dark\"] { --Surface: #111; }\n.b {}\n@media \
+             (prefers-color-scheme: dark) {\n:root:not([data-theme]) { --Surface: #111; }\n}\n"
+        );
+        assert!(themes.errors.is_empty());
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let (_, themes) = expand("@theme dark { --A: 1; }\n@theme light {}\n@theme dark {}");
+        assert_eq!(themes.names, vec!["dark", "light"]);
+    }
+
+    #[test]
+    fn ignored() {
+        assert_eq!(expand(".a { content: '@theme a {}'; }").0, None);
+        assert_eq!(expand("/* @theme a {} */").0, None);
+        assert_eq!(expand("@themes a {}").0, None);
+    }
+
+    #[test]
+    fn invalid_themes() {
+        assert_eq!(expand("@theme a-b {}").1.errors.len(), 1);
+        assert_eq!(expand("@theme a { .b {} }").1.errors.len(), 1);
+        assert_eq!(expand("@media print { @theme a {} }").1.errors.len(), 1);
+        assert_eq!(expand("@theme a;").1.errors.len(), 1);
+    }
+
+    #[test]
+    fn error_offsets() {
+        let offsets = |source| -> Vec<usize> {
+            expand(source)
+                .1
+                .errors
+                .iter()
+                .map(|err| err.offset)
+                .collect()
+        };
+        assert_eq!(offsets(".a {}\n@theme a-b {}"), [6]);
+        assert_eq!(offsets("@theme a {\n  .b {} }"), [16]);
+        assert_eq!(offsets("@media print {\n  @theme a {} }"), [17]);
+        assert_eq!(offsets("@theme a { --A: 1;"), [9]);
+    }
+}
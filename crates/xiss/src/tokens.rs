@@ -0,0 +1,367 @@
+use std::collections::BTreeMap;
+
+use rustc_hash::FxHashMap;
+use serde_json::{Map, Value};
+use swc_atoms::JsWord;
+use swc_common::DUMMY_SP;
+use swc_css::ast::ComponentValue;
+
+use crate::css::parse_declaration_value;
+
+/// Design token declared in a token group.
+struct Token<'a> {
+    value: &'a Value,
+    /// Token type, inherited from parent groups when it isn't declared.
+    ty: Option<&'a str>,
+}
+
+/// Token with resolved aliases.
+#[derive(Clone)]
+struct ResolvedToken {
+    value: Value,
+    ty: Option<String>,
+}
+
+/// Extracts const values from a design tokens file in the [W3C Design
+/// Tokens](https://design-tokens.github.io/community-group/format/) format.
+pub fn extract_token_values(
+    contents: &str,
+) -> Result<FxHashMap<JsWord, Vec<ComponentValue>>, String> {
+    let root: Value = serde_json::from_str(contents).map_err(|err| err.to_string())?;
+    let mut values = FxHashMap::default();
+    for (name, value) in flatten_tokens(&root)? {
+        let parsed = parse_declaration_value(&format!("--{}", name), &value, DUMMY_SP)
+            .ok_or_else(|| format!("Invalid value of a token '{}': {}", name, value))?;
+        values.insert(JsWord::from(name), parsed);
+    }
+    Ok(values)
+}
+
+/// Flattens design tokens into const names and CSS values, e.g. token
+/// `color.brand.primary` is converted to `color-brand-primary` that is used
+/// as `const(--color-brand-primary)`. Names are stored without `--` prefix
+/// like names of const values.
+pub fn flatten_tokens(root: &Value) -> Result<Vec<(String, String)>, String> {
+    let root = root
+        .as_object()
+        .ok_or_else(|| "Design tokens file should contain an object".to_string())?;
+    let mut tokens = BTreeMap::new();
+    collect_tokens(root, &mut Vec::new(), None, &mut tokens)?;
+
+    let mut resolved = FxHashMap::default();
+    let mut names: FxHashMap<String, &str> = FxHashMap::default();
+    let mut result = Vec::with_capacity(tokens.len());
+    for path in tokens.keys() {
+        let token = resolve_token(path, &tokens, &mut resolved, &mut Vec::new())?;
+        let name = path.replace('.', "-");
+        if let Some(other) = names.insert(name.clone(), path) {
+            return Err(format!(
+                "Tokens '{}' and '{}' have the same name '--{}'",
+                other, path, name
+            ));
+        }
+        let value = token_to_css(&token.value, token.ty.as_deref())
+            .map_err(|err| format!("Invalid token '{}': {}", path, err))?;
+        result.push((name, value));
+    }
+    Ok(result)
+}
+
+fn collect_tokens<'a>(
+    group: &'a Map<String, Value>,
+    path: &mut Vec<&'a str>,
+    ty: Option<&'a str>,
+    tokens: &mut BTreeMap<String, Token<'a>>,
+) -> Result<(), String> {
+    let ty = group.get("$type").and_then(|ty| ty.as_str()).or(ty);
+    for (key, value) in group.iter() {
+        // group properties
+        if key.starts_with('$') {
+            continue;
+        }
+        if key.is_empty()
+            || !key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!(
+                "Invalid token name '{}', names should contain only alphanumeric characters, '-' \
+                 and '_'",
+                key
+            ));
+        }
+        path.push(key);
+        match value {
+            Value::Object(object) if object.contains_key("$value") => {
+                tokens.insert(
+                    path.join("."),
+                    Token {
+                        value: &object["$value"],
+                        ty: object.get("$type").and_then(|ty| ty.as_str()).or(ty),
+                    },
+                );
+            }
+            Value::Object(object) => collect_tokens(object, path, ty, tokens)?,
+            _ => {
+                return Err(format!(
+                    "Invalid token '{}', tokens should be objects with a '$value' property",
+                    path.join(".")
+                ));
+            }
+        }
+        path.pop();
+    }
+    Ok(())
+}
+
+/// Resolves aliases in a token value, e.g. `"{color.brand.primary}"`.
+fn resolve_token(
+    path: &str,
+    tokens: &BTreeMap<String, Token>,
+    resolved: &mut FxHashMap<String, ResolvedToken>,
+    stack: &mut Vec<String>,
+) -> Result<ResolvedToken, String> {
+    if let Some(token) = resolved.get(path) {
+        return Ok(token.clone());
+    }
+    if stack.iter().any(|p| p == path) {
+        stack.push(path.to_string());
+        return Err(format!("Circular token alias {}", stack.join(" -> ")));
+    }
+    let token = tokens.get(path).ok_or_else(|| {
+        if let Some(from) = stack.last() {
+            format!("Unresolved alias '{{{}}}' in token '{}'", path, from)
+        } else {
+            format!("Unable to find token '{}'", path)
+        }
+    })?;
+    stack.push(path.to_string());
+    let mut ty = token.ty.map(|ty| ty.to_string());
+    let value = resolve_aliases(token.value, &mut ty, tokens, resolved, stack)?;
+    stack.pop();
+    let token = ResolvedToken { value, ty };
+    resolved.insert(path.to_string(), token.clone());
+    Ok(token)
+}
+
+fn resolve_aliases(
+    value: &Value,
+    ty: &mut Option<String>,
+    tokens: &BTreeMap<String, Token>,
+    resolved: &mut FxHashMap<String, ResolvedToken>,
+    stack: &mut Vec<String>,
+) -> Result<Value, String> {
+    match value {
+        Value::String(s) => {
+            if let Some(alias) = s.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+                let token = resolve_token(alias, tokens, resolved, stack)?;
+                // aliases inherit types from referenced tokens
+                if ty.is_none() {
+                    *ty = token.ty;
+                }
+                Ok(token.value)
+            } else {
+                Ok(value.clone())
+            }
+        }
+        Value::Array(items) => items
+            .iter()
+            .map(|item| resolve_aliases(item, &mut None, tokens, resolved, stack))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array),
+        Value::Object(object) => object
+            .iter()
+            .map(|(key, item)| {
+                resolve_aliases(item, &mut None, tokens, resolved, stack)
+                    .map(|item| (key.clone(), item))
+            })
+            .collect::<Result<Map<_, _>, _>>()
+            .map(Value::Object),
+        _ => Ok(value.clone()),
+    }
+}
+
+/// Converts a resolved token value to a CSS value.
+fn token_to_css(value: &Value, ty: Option<&str>) -> Result<String, String> {
+    match (value, ty) {
+        (Value::String(s), _) => Ok(s.clone()),
+        (Value::Number(n), _) => Ok(n.to_string()),
+        (Value::Object(object), Some("dimension" | "duration")) => {
+            match (object.get("value"), object.get("unit")) {
+                (Some(Value::Number(n)), Some(Value::String(unit))) => Ok(format!("{}{}", n, unit)),
+                _ => Err("dimension should have a numeric 'value' and a 'unit'".to_string()),
+            }
+        }
+        (Value::Array(items), Some("cubicBezier")) if items.len() == 4 => Ok(format!(
+            "cubic-bezier({})",
+            join(
+                items.iter().map(|item| token_to_css(item, Some("number"))),
+                ", "
+            )?
+        )),
+        (Value::Array(items), Some("fontFamily")) => join(
+            items.iter().map(|item| match item {
+                Value::String(s) if s.contains(' ') => Ok(serde_json::to_string(s).unwrap()),
+                _ => token_to_css(item, None),
+            }),
+            ", ",
+        ),
+        (Value::Array(items), Some("shadow")) => join(
+            items.iter().map(|item| token_to_css(item, Some("shadow"))),
+            ", ",
+        ),
+        (Value::Object(object), Some("shadow")) => {
+            let mut parts = Vec::new();
+            if object.get("inset") == Some(&Value::Bool(true)) {
+                parts.push("inset".to_string());
+            }
+            for key in ["offsetX", "offsetY", "blur", "spread"] {
+                if let Some(v) = object.get(key) {
+                    parts.push(token_to_css(v, Some("dimension"))?);
+                }
+            }
+            if let Some(color) = object.get("color") {
+                parts.push(token_to_css(color, Some("color"))?);
+            }
+            Ok(parts.join(" "))
+        }
+        (Value::Object(object), Some("border")) => {
+            let mut parts = Vec::new();
+            for (key, ty) in [
+                ("width", "dimension"),
+                ("style", "strokeStyle"),
+                ("color", "color"),
+            ] {
+                if let Some(v) = object.get(key) {
+                    parts.push(token_to_css(v, Some(ty))?);
+                }
+            }
+            Ok(parts.join(" "))
+        }
+        (Value::Object(_) | Value::Array(_), ty) => Err(format!(
+            "composite values with a type '{}' aren't supported",
+            ty.unwrap_or("unknown")
+        )),
+        (Value::Bool(_) | Value::Null, _) => {
+            Err("value should be a string, a number or a composite value".to_string())
+        }
+    }
+}
+
+fn join<I: Iterator<Item = Result<String, String>>>(items: I, sep: &str) -> Result<String, String> {
+    Ok(items.collect::<Result<Vec<_>, _>>()?.join(sep))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn flatten(value: Value) -> Result<Vec<(String, String)>, String> {
+        flatten_tokens(&value)
+    }
+
+    fn pairs(values: &[(&str, &str)]) -> Vec<(String, String)> {
+        values
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn flatten_groups() {
+        assert_eq!(
+            flatten(json!({
+                "color": {
+                    "$type": "color",
+                    "brand": {
+                        "primary": { "$value": "#0050ff" },
+                    },
+                },
+                "space": {
+                    "sm": { "$value": { "value": 4, "unit": "px" }, "$type": "dimension" },
+                },
+            }))
+            .unwrap(),
+            pairs(&[("color-brand-primary", "#0050ff"), ("space-sm", "4px")])
+        );
+    }
+
+    #[test]
+    fn aliases() {
+        assert_eq!(
+            flatten(json!({
+                "color": {
+                    "blue": { "$value": "#0050ff" },
+                    "primary": { "$value": "{color.blue}" },
+                    "link": { "$value": "{color.primary}" },
+                },
+            }))
+            .unwrap(),
+            pairs(&[
+                ("color-blue", "#0050ff"),
+                ("color-link", "#0050ff"),
+                ("color-primary", "#0050ff"),
+            ])
+        );
+    }
+
+    #[test]
+    fn alias_types() {
+        assert_eq!(
+            flatten(json!({
+                "gap": { "$value": { "value": 8, "unit": "px" }, "$type": "dimension" },
+                "margin": { "$value": "{gap}" },
+            }))
+            .unwrap(),
+            pairs(&[("gap", "8px"), ("margin", "8px")])
+        );
+    }
+
+    #[test]
+    fn composite_values() {
+        assert_eq!(
+            flatten(json!({
+                "ease": { "$type": "cubicBezier", "$value": [0.5, 0, 1, 1] },
+                "font": { "$type": "fontFamily", "$value": ["Helvetica Neue", "sans-serif"] },
+                "black": { "$value": "#000" },
+                "shadow": {
+                    "$type": "shadow",
+                    "$value": {
+                        "color": "{black}",
+                        "offsetX": "0px",
+                        "offsetY": "1px",
+                        "blur": "2px",
+                    },
+                },
+            }))
+            .unwrap(),
+            pairs(&[
+                ("black", "#000"),
+                ("ease", "cubic-bezier(0.5, 0, 1, 1)"),
+                ("font", "\"Helvetica Neue\", sans-serif"),
+                ("shadow", "0px 1px 2px #000"),
+            ])
+        );
+    }
+
+    #[test]
+    fn unresolved_aliases() {
+        let err = flatten(json!({ "a": { "$value": "{b.c}" } })).unwrap_err();
+        assert!(err.contains("'{b.c}'"), "{}", err);
+        assert!(flatten(json!({
+            "a": { "$value": "{b}" },
+            "b": { "$value": "{a}" },
+        }))
+        .unwrap_err()
+        .contains("Circular"));
+    }
+
+    #[test]
+    fn invalid_tokens() {
+        assert!(flatten(json!({ "a": 1 })).is_err());
+        assert!(flatten(json!({ "a b": { "$value": 1 } })).is_err());
+        assert!(flatten(json!({ "a": { "b": { "$value": 1 } }, "a-b": { "$value": 2 } })).is_err());
+    }
+}
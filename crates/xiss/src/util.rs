@@ -0,0 +1,11 @@
+use std::hash::{Hash, Hasher};
+
+use rustc_hash::FxHasher;
+
+/// Returns a hash value, hash values are stable only for the same
+/// [manifest_version](crate::manifest::manifest_version).
+pub fn hash<T: Hash + ?Sized>(value: &T) -> u64 {
+    let mut hasher = FxHasher::default();
+    value.hash(&mut hasher);
+    hasher.finish()
+}